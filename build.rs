@@ -0,0 +1,34 @@
+use std::fmt::Write;
+use std::path::Path;
+
+const SHELLCHECK_CODES: &str = "data/shellcheck-codes.tsv";
+
+/// Generate a table of Shellcheck code explanations, sorted by code.
+fn shellcheck_codes(input: &str) -> String {
+    let mut codes: Vec<(i32, &str, &str)> = (input.lines().enumerate())
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| {
+            let mut fields = line.split('\t');
+            let code = fields.next().and_then(|code| code.parse().ok());
+            (code.zip(fields.next()).zip(fields.next()))
+                .map(|((code, title), rationale)| (code, title, rationale))
+                .unwrap_or_else(|| panic!("{SHELLCHECK_CODES}:{}: Malformed line", index + 1))
+        })
+        .collect();
+    codes.sort_unstable_by_key(|&(code, _, _)| code);
+
+    let mut output = String::from("pub const EXPLANATIONS: &[(i32, &str, &str)] = &[\n");
+    for (code, title, rationale) in codes {
+        writeln!(output, "    ({code}, {title:?}, {rationale:?}),").unwrap();
+    }
+    output.push_str("];\n");
+    output
+}
+
+fn main() {
+    println!("cargo::rerun-if-changed={SHELLCHECK_CODES}");
+    let input = std::fs::read_to_string(SHELLCHECK_CODES).expect("Failed to read Shellcheck codes");
+    let directory = std::env::var("OUT_DIR").expect("OUT_DIR should be set by Cargo");
+    let output = Path::new(&directory).join("shellcheck_codes.rs");
+    std::fs::write(output, shellcheck_codes(&input)).expect("Failed to write Shellcheck codes");
+}
@@ -0,0 +1,575 @@
+use crate::config::Settings;
+use crate::shell::Shell;
+use crate::{db, lex, lsp, parse};
+
+fn get_line(text: &str, line: u32) -> Option<&str> {
+    text.lines().nth(line as usize)
+}
+
+/// Escape `literal` so that it can replace an expansion with the given quoting.
+pub fn quote_for(literal: &str, quoting: db::Quoting) -> String {
+    match quoting {
+        db::Quoting::DoubleQuotes => lex::quote(literal, Some('"')),
+        db::Quoting::HereDocument => {
+            let mut string = String::with_capacity(literal.len());
+            for char in literal.chars() {
+                if "$`\\".contains(char) {
+                    string.push('\\');
+                }
+                string.push(char);
+            }
+            string
+        }
+        db::Quoting::Unquoted => {
+            let is_plain = !literal.is_empty()
+                && !literal.starts_with('~')
+                && !literal.contains('\n')
+                && lex::quote(literal, None) == literal;
+            if is_plain {
+                String::from(literal)
+            }
+            else {
+                format!("'{}'", lex::quote(literal, Some('\'')))
+            }
+        }
+    }
+}
+
+/// Characters that cause field splitting or pathname expansion when they result from an unquoted
+/// expansion.
+const UNQUOTED_EXPANDED: [char; 6] = [' ', '\t', '\n', '*', '?', '['];
+
+/// Characters that cause expansions or globbing in an unquoted argument, but not in the value of an
+/// assignment or a quoted expansion.
+const UNQUOTED_ARGUMENT_SPECIAL: &str = "$`*?[{~";
+
+/// The range of the `$name` or `${name}` expansion around a reference to `name`.
+fn expansion_range(text: &str, name: lsp::Range) -> Option<lsp::Range> {
+    let line: Vec<char> = get_line(text, name.start.line)?.chars().collect();
+    let (start, end) = (name.start.character as usize, name.end.character as usize);
+    let range = |before: u32, after: u32| lsp::Range {
+        start: lsp::Position { line: name.start.line, character: name.start.character - before },
+        end: name.end.horizontal_offset(after),
+    };
+    if start >= 2 && line[start - 2..start] == ['$', '{'] && line.get(end) == Some(&'}') {
+        Some(range(2, 1))
+    }
+    else if start >= 1 && line[start - 1] == '$' {
+        Some(range(1, 0))
+    }
+    else {
+        None
+    }
+}
+
+/// If the assignment at `name` is a statement on a line of its own, return the range of the line.
+fn assignment_line(text: &str, name: db::Location, value: db::Location) -> Option<lsp::Range> {
+    let line = get_line(text, name.range.start.line)?;
+    let assignment = &text[name.view.start as usize..value.view.end as usize];
+    (line.trim() == assignment).then(|| lsp::Range {
+        start: lsp::Position { line: name.range.start.line, character: 0 },
+        end: lsp::Position { line: name.range.start.line + 1, character: 0 },
+    })
+}
+
+/// Replace every read of the variable at `target` with the literal value it is assigned once.
+/// The assignment is removed if it is a statement on a line of its own.
+pub fn inline_variable(document: &db::Document, target: db::SymbolReference) -> Option<db::Action> {
+    let info = &document.info;
+    let symbol = &info.symbols[target.id];
+    let db::SymbolKind::Variable(id) = symbol.kind else { return None };
+    let variable = &info.variables[id];
+    if matches!(variable.kind, db::VariableKind::Environment) {
+        return None;
+    }
+    let value = variable.value?;
+    let (None, literal) = lex::unquote(value.view.string(&document.text))? else { return None };
+    // An unquoted read is split into fields and expanded as a glob, unlike the quoted literal.
+    let is_split = literal.is_empty() || literal.contains(UNQUOTED_EXPANDED);
+
+    let mut edits = Vec::new();
+    let mut writes = 0;
+    for &index in &symbol.ref_indices {
+        let reference = info.references[index as usize];
+        match reference.reference.kind {
+            lsp::ReferenceKind::Write => writes += 1,
+            lsp::ReferenceKind::Read if is_split && reference.quoting == db::Quoting::Unquoted => {
+                return None;
+            }
+            lsp::ReferenceKind::Read => edits.push(lsp::TextEdit {
+                range: expansion_range(&document.text, reference.reference.range)?,
+                new_text: quote_for(&literal, reference.quoting),
+            }),
+        }
+    }
+    if writes != 1 || edits.is_empty() {
+        return None;
+    }
+    if let Some(range) = assignment_line(&document.text, variable.first_assignment?, value) {
+        edits.push(lsp::TextEdit { range, new_text: String::new() });
+    }
+    let title = format!("Inline variable `{}`", symbol.name);
+    Some(db::Action { kind: db::ActionKind::Edit { title, edits }, range: target.reference.range })
+}
+
+fn unused_name(info: &db::DocumentInfo, base: &str) -> String {
+    let is_used = |name: &str| info.symbols.underlying.iter().any(|symbol| symbol.name == name);
+    (1..)
+        .map(|n| if n == 1 { String::from(base) } else { format!("{base}{n}") })
+        .find(|name| !is_used(name))
+        .expect("should find an unused name")
+}
+
+/// Assign the argument at `selection` to a new variable on the line before the command, and pass
+/// the variable instead. Arguments with unquoted expansions or globs are not extracted, since
+/// their results would no longer be split or expanded. The command must begin both a statement
+/// and its line, so that the assignment is a statement of its own, evaluated just before the
+/// command. This excludes commands after `case` patterns, `then`, or `&&`, for example.
+pub fn extract_variable(document: &db::Document, selection: lsp::Range) -> Option<db::Action> {
+    let info = &document.info;
+    let (call, argument) = info.calls.iter().find_map(|call| {
+        call.arguments.iter().find(|argument| argument.range == selection).map(|arg| (call, arg))
+    })?;
+    let text = argument.view.string(&document.text);
+    let line = get_line(&document.text, call.name.range.start.line)?;
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let at_line_start = indent.chars().count() as u32 == call.name.range.start.character;
+    if !at_line_start
+        || !info.statement_starts.contains(&call.name.range.start)
+        || lex::contains_unquoted(text, UNQUOTED_ARGUMENT_SPECIAL)
+    {
+        return None;
+    }
+
+    let name = unused_name(info, "value");
+    let position = lsp::Position { line: call.name.range.start.line, character: 0 };
+    let edits = vec![
+        lsp::TextEdit {
+            range: lsp::Range { start: position, end: position },
+            new_text: format!("{indent}{name}={text}\n"),
+        },
+        lsp::TextEdit { range: argument.range, new_text: format!("\"${name}\"") },
+    ];
+    let title = String::from("Extract to variable");
+    Some(db::Action { kind: db::ActionKind::Edit { title, edits }, range: argument.range })
+}
+
+/// Words that can not be the names of commands in a complete statement.
+const CLOSING_WORDS: &[&str] = &["then", "else", "elif", "fi", "do", "done", "esac", "}", "]]"];
+
+/// Commands that behave differently when they are moved into a new function.
+const SCOPED_COMMANDS: &[&str] = &["return", "break", "continue", "shift", "local"];
+
+/// Whether `text` consists of complete statements that can be moved into a new function. The
+/// statements must not use `$#`, which would count the arguments of the new function.
+fn is_extractable(text: &str, shell: Shell) -> bool {
+    let mut settings = Settings { default_shell: shell, ..Settings::default() };
+    settings.environment.variables = false;
+    settings.environment.executables = false;
+    let info = parse::parse(text, &settings);
+    let is_error = |diagnostic: &lsp::Diagnostic| diagnostic.severity == lsp::Severity::Error;
+    !info.diagnostics.iter().any(is_error)
+        && !lex::Lexer::new(text).any(|token| token.kind == lex::TokenKind::DollarHash)
+        && !info.calls.iter().any(|call| {
+            let name = call.name.view.string(text);
+            CLOSING_WORDS.contains(&name) || SCOPED_COMMANDS.contains(&name)
+        })
+}
+
+fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// The indentation of the first indented line of `text`, or a tab.
+fn indentation_unit(text: &str) -> &str {
+    (text.lines())
+        .filter(|line| !line.trim().is_empty())
+        .map(indentation)
+        .find(|indent| !indent.is_empty())
+        .unwrap_or("\t")
+}
+
+/// The line before which a function should be placed so that it precedes the top-level statement
+/// at `line`, along with any comments above the statement.
+fn definition_line(document: &db::Document, line: u32) -> u32 {
+    let statements = &document.info.statements;
+    let index = statements.partition_point(|start| start.line <= line);
+    let mut line = index.checked_sub(1).map_or(line, |index| statements[index].line);
+    let lines: Vec<&str> = document.text.lines().collect();
+    while line > 0 && lines[line as usize - 1].trim_start().starts_with('#') {
+        if line == 1 && lines[0].starts_with("#!") {
+            break;
+        }
+        line -= 1;
+    }
+    line
+}
+
+/// Move the statements on the lines covered by `selection` into a new function, placed before the
+/// current top-level statement, and call it instead. Variables read by the statements are passed
+/// as arguments, and variables written by them that are not referenced elsewhere become local,
+/// unless the shell is POSIX shell where `local` is not specified.
+pub fn extract_function(document: &db::Document, selection: lsp::Range) -> Option<db::Action> {
+    let info = &document.info;
+    if selection.start == selection.end {
+        return None;
+    }
+    let first = selection.start.line;
+    let last = if selection.end.character == 0 && selection.end.line > first {
+        selection.end.line - 1
+    }
+    else {
+        selection.end.line
+    };
+    let mut lines: Vec<String> = (document.text.lines())
+        .skip(first as usize)
+        .take((last - first + 1) as usize)
+        .map(String::from)
+        .collect();
+    if lines.iter().all(|line| line.trim().is_empty())
+        || !is_extractable(&(lines.join("\n") + "\n"), info.shell)
+    {
+        return None;
+    }
+    let range = lsp::Range {
+        start: lsp::Position { line: first, character: 0 },
+        end: lsp::Position { line: last + 1, character: 0 },
+    };
+
+    let mut parameters: Vec<db::SymbolId> = Vec::new();
+    let mut assigned: Vec<db::SymbolId> = Vec::new();
+    let mut locals: Vec<String> = Vec::new();
+    let mut replacements = Vec::new();
+    for reference in info.references.iter().filter(|r| range.contains_range(r.reference.range)) {
+        let symbol = &info.symbols[reference.id];
+        let variable = match symbol.kind {
+            db::SymbolKind::Variable(id) => &info.variables[id],
+            db::SymbolKind::Parameter(_)
+            | db::SymbolKind::Special(db::Special::At | db::Special::Star) => return None,
+            _ => continue,
+        };
+        if matches!(variable.kind, db::VariableKind::Environment) {
+            continue;
+        }
+        if let Some(index) = parameters.iter().position(|&id| id == reference.id) {
+            if reference.reference.kind == lsp::ReferenceKind::Write {
+                return None;
+            }
+            replacements.push((reference.reference.range, index + 1));
+        }
+        else if reference.reference.kind == lsp::ReferenceKind::Read {
+            if !assigned.contains(&reference.id) {
+                parameters.push(reference.id);
+                replacements.push((reference.reference.range, parameters.len()));
+            }
+        }
+        else if !assigned.contains(&reference.id) {
+            assigned.push(reference.id);
+            let is_outside = |&index: &u32| {
+                !range.contains_range(info.references[index as usize].reference.range)
+            };
+            if !symbol.ref_indices.iter().any(is_outside) {
+                locals.push(symbol.name.clone());
+            }
+        }
+    }
+
+    // Replace the expansions of the parameters, starting from the end of each line.
+    for (name, index) in replacements.into_iter().rev() {
+        let line = &mut lines[(name.start.line - first) as usize];
+        let chars: Vec<char> = line.chars().collect();
+        let (mut start, end) = (name.start.character as usize, name.end.character as usize);
+        let new_text = if start > 0 && chars[start - 1] == '$' {
+            start -= 1;
+            if index < 10 { format!("${index}") } else { format!("${{{index}}}") }
+        }
+        else {
+            index.to_string()
+        };
+        let prefix: String = chars[..start].iter().collect();
+        let suffix: String = chars[end..].iter().collect();
+        *line = format!("{prefix}{new_text}{suffix}");
+    }
+
+    // Here-document bodies and delimiters are copied unchanged, since indentation is part of their
+    // content.
+    let in_heredoc: Vec<bool> = (first..=last)
+        .map(|line| {
+            (info.heredocs.iter())
+                .any(|heredoc| (heredoc.start.line..heredoc.end.line).contains(&line))
+        })
+        .collect();
+    let indent = (lines.iter().zip(&in_heredoc))
+        .filter(|&(line, &in_heredoc)| !in_heredoc && !line.trim().is_empty())
+        .map(|(line, _)| indentation(line))
+        .min_by_key(|indent| indent.len())
+        .unwrap_or_default()
+        .to_owned();
+    let unit = indentation_unit(&document.text);
+    let name = unused_name(info, "new_function");
+
+    let mut definition = String::new();
+    for &id in &parameters {
+        definition.push_str(&format!("##@ param {}\n", info.symbols[id].name));
+    }
+    definition.push_str(&format!("{name}() {{\n"));
+    if !locals.is_empty() && info.shell != Shell::Posix {
+        definition.push_str(&format!("{unit}local {}\n", locals.join(" ")));
+    }
+    for (line, in_heredoc) in lines.iter().zip(in_heredoc) {
+        if in_heredoc {
+            definition.push_str(line);
+        }
+        else {
+            // Lines indented differently from the others, like with tabs instead of spaces, keep
+            // their content.
+            let line = line.strip_prefix(indent.as_str()).unwrap_or(line.trim_start());
+            if !line.is_empty() {
+                definition.push_str(unit);
+                definition.push_str(line);
+            }
+        }
+        definition.push('\n');
+    }
+    definition.push_str("}\n\n");
+
+    let mut call = format!("{indent}{name}");
+    for &id in &parameters {
+        call.push_str(&format!(" \"${}\"", info.symbols[id].name));
+    }
+    call.push('\n');
+
+    let position = lsp::Position { line: definition_line(document, first), character: 0 };
+    let edits = vec![
+        lsp::TextEdit {
+            range: lsp::Range { start: position, end: position },
+            new_text: definition,
+        },
+        lsp::TextEdit { range, new_text: call },
+    ];
+    let title = String::from("Extract to function");
+    Some(db::Action { kind: db::ActionKind::Edit { title, edits }, range: selection })
+}
+
+/// The highest positional parameter read in scope, where each `shift` moves the following
+/// parameters down. The index of a parameter is given by `index` if it is in scope.
+fn highest_parameter(
+    info: &db::DocumentInfo,
+    text: &str,
+    index: impl Fn(db::Parameter) -> Option<u16>,
+    in_scope: impl Fn(lsp::Range) -> bool,
+) -> usize {
+    let shifts: Vec<(lsp::Position, usize)> = (info.calls.iter())
+        .filter(|call| call.name.view.string(text) == "shift" && in_scope(call.name.range))
+        .map(|call| {
+            // A count that is not a literal number is assumed to be one.
+            let count = call.arguments.first().map(|argument| argument.view.string(text));
+            (call.name.range.start, count.and_then(|count| count.parse().ok()).unwrap_or(1))
+        })
+        .collect();
+    (info.references.iter())
+        .filter(|reference| in_scope(reference.reference.range))
+        .filter_map(|reference| match info.symbols[reference.id].kind {
+            db::SymbolKind::Parameter(parameter) => index(parameter).map(|index| {
+                let start = reference.reference.range.start;
+                let shifted: usize =
+                    shifts.iter().filter(|&&(shift, _)| shift < start).map(|&(_, n)| n).sum();
+                index as usize + shifted
+            }),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn insert_lines(line: u32, indent: &str, lines: &[&str]) -> lsp::TextEdit {
+    let position = lsp::Position { line, character: 0 };
+    lsp::TextEdit {
+        range: lsp::Range { start: position, end: position },
+        new_text: lines.iter().map(|annotation| format!("{indent}{annotation}\n")).collect(),
+    }
+}
+
+/// Insert `desc` and `param` annotations above the definition of the function at `target`, with a
+/// `param` annotation for each positional parameter that the function uses.
+pub fn annotate_function(
+    document: &db::Document,
+    target: db::SymbolReference,
+) -> Option<db::Action> {
+    let info = &document.info;
+    let db::SymbolKind::Function(id) = info.symbols[target.id].kind else { return None };
+    let function = &info.functions[id];
+    let definition = function.definition?;
+    if target.reference.kind != lsp::ReferenceKind::Write
+        || !definition.range.contains(target.reference.range.start)
+        || function.description.is_some()
+        || !function.parameters.is_empty()
+    {
+        return None;
+    }
+    let index = |parameter| match parameter {
+        db::Parameter::Function { id: function, index } if function == id => Some(index),
+        _ => None,
+    };
+    let count = highest_parameter(info, &document.text, index, |range| {
+        definition.range.contains_range(range)
+    });
+    let line = definition.range.start.line;
+    let indent = indentation(get_line(&document.text, line)?);
+    let mut lines = vec!["##@ desc"];
+    lines.resize(count + 1, "##@ param");
+    let edits = vec![insert_lines(line, indent, &lines)];
+    let title = String::from("Generate annotations");
+    Some(db::Action { kind: db::ActionKind::Edit { title, edits }, range: target.reference.range })
+}
+
+/// Insert a `script` annotation block at the top of the script, with a `param` annotation for each
+/// positional parameter that the script uses outside of functions.
+pub fn annotate_script(document: &db::Document, selection: lsp::Range) -> Option<db::Action> {
+    let info = &document.info;
+    if selection.start.line != 0 || info.script_parameters.is_some() {
+        return None;
+    }
+    let index = |parameter| match parameter {
+        db::Parameter::Script { index } => Some(index),
+        db::Parameter::Function { .. } => None,
+    };
+    let definitions: Vec<lsp::Range> = (info.functions.underlying.iter())
+        .filter_map(|function| function.definition.map(|definition| definition.range))
+        .collect();
+    let count = highest_parameter(info, &document.text, index, |range| {
+        !definitions.iter().any(|definition| definition.contains_range(range))
+    });
+    if count == 0 {
+        return None;
+    }
+    let mut lines = vec!["##@ param"; count];
+    lines.push("##@ script");
+    let line = if document.text.starts_with("#!") { 1 } else { 0 };
+    let edits = vec![insert_lines(line, "", &lines)];
+    let title = String::from("Generate script annotations");
+    Some(db::Action { kind: db::ActionKind::Edit { title, edits }, range: selection })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Settings;
+    use crate::db::{self, Quoting};
+    use crate::lsp;
+
+    fn document(text: &str) -> db::Document {
+        let mut settings = Settings::default();
+        settings.environment.variables = false;
+        settings.environment.executables = false;
+        let info = crate::parse::parse(text, &settings);
+        db::Document { text: String::from(text), info, ..db::Document::default() }
+    }
+
+    fn edits(action: Option<db::Action>) -> Vec<String> {
+        match action.map(|action| action.kind) {
+            Some(db::ActionKind::Edit { edits, .. }) => {
+                edits.into_iter().map(|edit| edit.new_text).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn lines(start: u32, end: u32) -> lsp::Range {
+        lsp::Range {
+            start: lsp::Position { line: start, character: 0 },
+            end: lsp::Position { line: end, character: 0 },
+        }
+    }
+
+    #[test]
+    fn quote_for() {
+        assert_eq!(super::quote_for("a", Quoting::Unquoted), "a");
+        assert_eq!(super::quote_for("a b", Quoting::Unquoted), "'a b'");
+        assert_eq!(super::quote_for("it's", Quoting::Unquoted), "'it'\\''s'");
+        assert_eq!(super::quote_for("", Quoting::Unquoted), "''");
+        assert_eq!(super::quote_for("~", Quoting::Unquoted), "'~'");
+        assert_eq!(super::quote_for("a \"$b\"", Quoting::DoubleQuotes), "a \\\"\\$b\\\"");
+        assert_eq!(super::quote_for("a \"$b\"", Quoting::HereDocument), "a \"\\$b\"");
+    }
+
+    #[test]
+    fn inline_variable() {
+        let inline = |text: &str| {
+            let document = document(text);
+            edits(super::inline_variable(&document, document.info.references[0]))
+        };
+        assert_eq!(inline("x='a b'\necho \"$x\"\n"), ["a b", ""]);
+        assert_eq!(inline("x=a\necho $x\n"), ["a", ""]);
+        assert!(inline("x='a b'\necho $x\n").is_empty());
+        assert!(inline("x='*'\necho $x\n").is_empty());
+        assert!(inline("x=''\necho $x\n").is_empty());
+    }
+
+    #[test]
+    fn extract_variable() {
+        let extract = |text: &str, line: u32, start: u32, end: u32| {
+            let range = lsp::Range {
+                start: lsp::Position { line, character: start },
+                end: lsp::Position { line, character: end },
+            };
+            edits(super::extract_variable(&document(text), range))
+        };
+        assert_eq!(extract("ls 'a b'\n", 0, 3, 8), ["value='a b'\n", "\"$value\""]);
+        assert_eq!(extract("ls \"$x\"\n", 0, 3, 7), ["value=\"$x\"\n", "\"$value\""]);
+        assert!(extract("ls *.sh\n", 0, 3, 7).is_empty());
+        assert!(extract("ls $x\n", 0, 3, 5).is_empty());
+        assert!(extract("ls $(pwd)\n", 0, 3, 9).is_empty());
+        assert!(extract("ls {a,b}\n", 0, 3, 8).is_empty());
+        assert_eq!(extract("if true; then\n  ls 'x y'\nfi\n", 1, 5, 10), [
+            "  value='x y'\n",
+            "\"$value\""
+        ]);
+        assert!(extract("case $1 in\n  a) ls 'x y' ;;\nesac\n", 1, 8, 13).is_empty());
+        assert!(extract("true && ls 'x y'\n", 0, 11, 16).is_empty());
+        assert!(extract("true |\n  ls 'x y'\n", 1, 5, 10).is_empty());
+    }
+
+    #[test]
+    fn annotate() {
+        let text = "#!/bin/sh\nf() {\n  echo \"$1\"\n  shift 2\n  echo \"$1\"\n}\necho \"$2\"\n";
+        let document = document(text);
+        let reference = document.info.references[0];
+        assert_eq!(edits(super::annotate_function(&document, reference)), [
+            "##@ desc\n##@ param\n##@ param\n##@ param\n"
+        ]);
+        assert_eq!(edits(super::annotate_script(&document, lines(0, 0))), [
+            "##@ param\n##@ param\n##@ script\n"
+        ]);
+    }
+
+    #[test]
+    fn extract_function() {
+        let text = "#!/bin/bash\nx=1\nif true; then\n  y=$x\n  echo \"$y\"\nfi\necho $x\n";
+        let document = document(text);
+        assert_eq!(edits(super::extract_function(&document, lines(3, 5))), [
+            "##@ param x\nnew_function() {\n  local y\n  y=$1\n  echo \"$y\"\n}\n\n",
+            "  new_function \"$x\"\n",
+        ]);
+        assert!(super::extract_function(&document, lines(2, 4)).is_none());
+        assert!(super::extract_function(&document, lines(6, 6)).is_none());
+    }
+
+    #[test]
+    fn extract_function_heredoc() {
+        let heredoc = document("f() {\n  cat <<EOF\nhi\nEOF\n  echo $#\n}\n");
+        assert_eq!(edits(super::extract_function(&heredoc, lines(1, 4))), [
+            "new_function() {\n  cat <<EOF\nhi\nEOF\n}\n\n",
+            "  new_function\n",
+        ]);
+        assert!(super::extract_function(&heredoc, lines(1, 5)).is_none());
+    }
+
+    #[test]
+    fn extract_function_mixed_indentation() {
+        let mixed = document("if true; then\n  a=1\n\tb=2\nfi\n");
+        assert_eq!(edits(super::extract_function(&mixed, lines(1, 3))), [
+            "new_function() {\n  a=1\n  b=2\n}\n\n",
+            "\tnew_function\n",
+        ]);
+    }
+}
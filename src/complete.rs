@@ -0,0 +1,986 @@
+use crate::external::options::CommandOption;
+use crate::external::shellcheck;
+use crate::indexvec::VecIndex;
+use crate::parse::CompletionPosition;
+use crate::shell::{Shell, BOURNE, EXTENDED};
+use crate::{db, lex, lsp, parse, paths, syntax};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// A template for a compound command, in the LSP snippet syntax.
+struct Snippet {
+    label: &'static str,
+    detail: &'static str,
+    body: &'static str,
+    shells: &'static [Shell],
+}
+
+const SNIPPETS: &[Snippet] = &[
+    Snippet {
+        label: "if",
+        detail: "if statement",
+        body: "if ${1:condition}; then\n\t$0\nfi",
+        shells: BOURNE,
+    },
+    Snippet {
+        label: "ifelse",
+        detail: "if statement with an else branch",
+        body: "if ${1:condition}; then\n\t$2\nelse\n\t$0\nfi",
+        shells: BOURNE,
+    },
+    Snippet {
+        label: "for",
+        detail: "for loop over words",
+        body: "for ${1:item} in ${2:words}; do\n\t$0\ndone",
+        shells: BOURNE,
+    },
+    Snippet {
+        label: "fori",
+        detail: "Arithmetic for loop",
+        body: "for ((${1:i} = 0; $1 < ${2:count}; $1++)); do\n\t$0\ndone",
+        shells: EXTENDED,
+    },
+    Snippet {
+        label: "while",
+        detail: "while loop",
+        body: "while ${1:condition}; do\n\t$0\ndone",
+        shells: BOURNE,
+    },
+    Snippet {
+        label: "until",
+        detail: "until loop",
+        body: "until ${1:condition}; do\n\t$0\ndone",
+        shells: BOURNE,
+    },
+    Snippet {
+        label: "whileread",
+        detail: "Loop over lines of input",
+        body: "while IFS= read -r ${1:line}; do\n\t$0\ndone",
+        shells: BOURNE,
+    },
+    Snippet {
+        label: "select",
+        detail: "select menu loop",
+        body: "select ${1:item} in ${2:words}; do\n\t$0\ndone",
+        shells: EXTENDED,
+    },
+    Snippet {
+        label: "case",
+        detail: "case statement",
+        body: "case ${1:\\$word} in\n\t${2:pattern})\n\t\t$0\n\t\t;;\nesac",
+        shells: BOURNE,
+    },
+    Snippet {
+        label: "function",
+        detail: "Function with annotations",
+        body: "##@ desc ${1:Description}\n##@ param ${2:Parameter}\n${3:name}() {\n\t$0\n}",
+        shells: BOURNE,
+    },
+    Snippet {
+        label: "getopts",
+        detail: "Option parsing loop",
+        body: "while getopts '${1:ab:}' ${2:option}; do\n\tcase \\$$2 in\n\t\t${3:a}) $0;;\n\
+               \t\t*) exit 2;;\n\tesac\ndone\nshift \\$((OPTIND - 1))",
+        shells: BOURNE,
+    },
+    Snippet {
+        label: "trap",
+        detail: "Cleanup handler run on exit",
+        body: "${1:cleanup}() {\n\t$0\n}\ntrap $1 EXIT",
+        shells: BOURNE,
+    },
+];
+
+/// An operator that may follow the parameter name in a `${name...}` expansion.
+struct ExpansionOperator {
+    operator: &'static str,
+    form: &'static str,
+    description: &'static str,
+    shells: &'static [Shell],
+}
+
+const EXPANSION_OPERATORS: &[ExpansionOperator] = &[
+    ExpansionOperator {
+        operator: ":-",
+        form: "${name:-word}",
+        description: "Use `word` if `name` is unset or empty, and the value of `name` otherwise.",
+        shells: BOURNE,
+    },
+    ExpansionOperator {
+        operator: ":=",
+        form: "${name:=word}",
+        description: "Assign `word` to `name` if it is unset or empty, then use the value of \
+                      `name`.",
+        shells: BOURNE,
+    },
+    ExpansionOperator {
+        operator: ":?",
+        form: "${name:?word}",
+        description: "Exit with `word` as the error message if `name` is unset or empty, and use \
+                      the value of `name` otherwise.",
+        shells: BOURNE,
+    },
+    ExpansionOperator {
+        operator: ":+",
+        form: "${name:+word}",
+        description: "Use `word` if `name` is set and not empty, and nothing otherwise.",
+        shells: BOURNE,
+    },
+    ExpansionOperator {
+        operator: "#",
+        form: "${name#pattern}",
+        description: "Remove the shortest prefix matching `pattern` from the value of `name`.",
+        shells: BOURNE,
+    },
+    ExpansionOperator {
+        operator: "##",
+        form: "${name##pattern}",
+        description: "Remove the longest prefix matching `pattern` from the value of `name`.",
+        shells: BOURNE,
+    },
+    ExpansionOperator {
+        operator: "%",
+        form: "${name%pattern}",
+        description: "Remove the shortest suffix matching `pattern` from the value of `name`.",
+        shells: BOURNE,
+    },
+    ExpansionOperator {
+        operator: "%%",
+        form: "${name%%pattern}",
+        description: "Remove the longest suffix matching `pattern` from the value of `name`.",
+        shells: BOURNE,
+    },
+    ExpansionOperator {
+        operator: "/",
+        form: "${name/pattern/string}",
+        description: "Replace the first match of `pattern` in the value of `name` with `string`.",
+        shells: EXTENDED,
+    },
+    ExpansionOperator {
+        operator: "//",
+        form: "${name//pattern/string}",
+        description: "Replace every match of `pattern` in the value of `name` with `string`.",
+        shells: EXTENDED,
+    },
+    ExpansionOperator {
+        operator: "^^",
+        form: "${name^^}",
+        description: "Convert the value of `name` to uppercase.",
+        shells: &[Shell::Bash],
+    },
+    ExpansionOperator {
+        operator: ",,",
+        form: "${name,,}",
+        description: "Convert the value of `name` to lowercase.",
+        shells: &[Shell::Bash],
+    },
+];
+
+/// Longer completion lists are truncated and marked incomplete, so that the client asks for a new
+/// list as the user types.
+const MAX_ITEMS: usize = 100;
+
+/// Candidates are ranked by tier before their match score, so that nearby definitions come first.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Tier {
+    Keyword,
+    Local,
+    Global,
+    Environment,
+    Function,
+    Snippet,
+    Builtin,
+    Executable,
+    Other,
+}
+
+struct Candidate {
+    tier: Tier,
+    item: lsp::CompletionItem,
+}
+
+/// The word under the cursor. `start` is a byte offset into the line.
+struct Word {
+    start: usize,
+    is_comment: bool,
+}
+
+/// Match `query` as a case-insensitive subsequence of `candidate`. Consecutive matches, exact case
+/// matches, and matches at the start of words score higher.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<u32> {
+    let mut score = 0;
+    let mut previous_match: Option<usize> = None;
+    let mut previous_char: Option<char> = None;
+    let mut chars = candidate.chars().enumerate();
+
+    for query_char in query.chars() {
+        loop {
+            let (index, char) = chars.next()?;
+            let before = previous_char.replace(char);
+            if !char.to_lowercase().eq(query_char.to_lowercase()) {
+                continue;
+            }
+            score += 1;
+            if char == query_char {
+                score += 1;
+            }
+            if previous_match.is_some_and(|previous| previous + 1 == index) {
+                score += 4;
+            }
+            let camel_hump = |before: char| before.is_lowercase() && char.is_uppercase();
+            if before.is_none_or(|before| !before.is_alphanumeric() || camel_hump(before)) {
+                score += 3;
+            }
+            previous_match = Some(index);
+            break;
+        }
+    }
+    Some(score)
+}
+
+/// Filter `candidates` by fuzzy matching their labels against `query`, then sort and truncate them.
+/// Prefix matches come before other matches, and within those, candidates are ordered by tier,
+/// score, and length.
+fn rank(query: &str, candidates: Vec<Candidate>) -> lsp::CompletionList {
+    let mut matches: Vec<(bool, Tier, u32, lsp::CompletionItem)> = (candidates.into_iter())
+        .filter_map(|Candidate { tier, item }| {
+            let score = fuzzy_score(query, &item.label)?;
+            Some((!item.label.starts_with(query), tier, score, item))
+        })
+        .collect();
+    matches.sort_by(|(a_fuzzy, a_tier, a_score, a), (b_fuzzy, b_tier, b_score, b)| {
+        (a_fuzzy.cmp(b_fuzzy))
+            .then(a_tier.cmp(b_tier))
+            .then(b_score.cmp(a_score))
+            .then(a.label.len().cmp(&b.label.len()))
+            .then(a.label.cmp(&b.label))
+    });
+
+    // Symbols that share a name, like locals in different functions, are listed once.
+    let mut seen = HashSet::new();
+    let mut items: Vec<lsp::CompletionItem> = (matches.into_iter())
+        .map(|(_, _, _, item)| item)
+        .filter(|item| seen.insert((item.label.clone(), item.kind as i32)))
+        .collect();
+
+    let is_incomplete = items.len() > MAX_ITEMS;
+    items.truncate(MAX_ITEMS);
+    for (index, item) in items.iter_mut().enumerate() {
+        item.sort_text = Some(format!("{index:03}"));
+        item.filter_text.get_or_insert_with(|| item.label.clone());
+    }
+    lsp::CompletionList { is_incomplete, items }
+}
+
+/// Find the start of the word that ends at the end of `prefix`, which may be a comment.
+/// What the word is depends on the parse state, see `parse::completion_context`.
+fn current_word(prefix: &str) -> Word {
+    let mut start = 0;
+    let mut quote = None;
+    let mut chars = prefix.char_indices().peekable();
+
+    while let Some((index, char)) = chars.next() {
+        match (quote, char) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (Some(_), '"') => quote = None,
+            (_, '\\') => _ = chars.next(),
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(char),
+            (None, '#') if index == start => return Word { start, is_comment: true },
+            (None, ' ' | '\t' | ';' | '|' | '&' | '(' | ')' | '`' | '<' | '>') => {
+                start = index + char.len_utf8();
+                if matches!(char, '<' | '>') {
+                    while let Some((index, char)) = chars.next_if(|&(_, c)| "<>&|-".contains(c)) {
+                        start = index + char.len_utf8();
+                    }
+                }
+            }
+            (None, _) => {}
+        }
+    }
+    Word { start, is_comment: false }
+}
+
+/// If `word` ends with `${name` followed by a partially typed expansion operator, return the
+/// offsets of the name and of the operator.
+fn expansion_operator_start(word: &str) -> Option<(usize, usize)> {
+    let name_start = word.rfind("${")? + 2;
+    let rest = &word[name_start..];
+    let name_width = match rest.chars().next()? {
+        '@' | '*' | '?' | '-' | '$' | '!' => 1,
+        _ => rest.find(|char: char| !char.is_alphanumeric() && char != '_').unwrap_or(rest.len()),
+    };
+    let operator = &rest[name_width..];
+    let is_operator_prefix = EXPANSION_OPERATORS.iter().any(|op| op.operator.starts_with(operator));
+    (name_width != 0 && is_operator_prefix).then_some((name_start, name_start + name_width))
+}
+
+/// If `word` ends with a partially typed parameter expansion such as `$na` or `${na`, return the
+/// offset of the parameter name.
+fn parameter_start(word: &str) -> Option<usize> {
+    let start = word.trim_end_matches(|char: char| char.is_alphanumeric() || char == '_').len();
+    let before = &word[..start];
+    (before.ends_with('$') || before.ends_with("${")).then_some(start)
+}
+
+/// Complete the final component of a partially typed path. Relative paths are resolved relative
+/// to `directory`, and paths beginning with `~/` relative to the home directory.
+fn path_completions(word: &str, range: lsp::Range, directory: &Path) -> lsp::CompletionList {
+    let Some((open_quote, literal)) = lex::unquote(word) else {
+        return lsp::CompletionList::default();
+    };
+    let (tilde, literal) = match literal.strip_prefix("~/") {
+        Some(rest) if word.starts_with("~/") => (true, rest),
+        _ => (false, literal.as_str()),
+    };
+    let base: PathBuf = if tilde {
+        let Some(home) = paths::home() else { return lsp::CompletionList::default() };
+        home
+    }
+    else {
+        directory.to_owned()
+    };
+    let split = literal.rfind('/').map_or(0, |index| index + 1);
+    let (head, name_prefix) = literal.split_at(split);
+    let Ok(entries) = std::fs::read_dir(base.join(head)) else {
+        return lsp::CompletionList::default();
+    };
+
+    let candidates = (entries.flatten())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if name.starts_with('.') && !name_prefix.starts_with('.') {
+                return None;
+            }
+            let is_directory = entry.path().is_dir();
+            let mut new_text = String::from(if tilde { "~/" } else { "" });
+            new_text.extend(open_quote);
+            new_text.push_str(&lex::quote(&format!("{head}{name}"), open_quote));
+            if is_directory {
+                new_text.push('/');
+            }
+            else {
+                new_text.extend(open_quote);
+            }
+            let item = lsp::CompletionItem {
+                label: if is_directory { format!("{name}/") } else { name },
+                kind: if is_directory {
+                    lsp::CompletionItemKind::Directory
+                }
+                else {
+                    lsp::CompletionItemKind::File
+                },
+                insert_text_format: None,
+                sort_text: None,
+                filter_text: Some(new_text.clone()),
+                edit: lsp::TextEdit { range, new_text },
+                detail: None,
+                documentation: None,
+                data: None,
+            };
+            Some(Candidate { tier: Tier::Other, item })
+        })
+        .collect();
+    rank(name_prefix, candidates)
+}
+
+fn option_completions(
+    options: &[CommandOption],
+    word: &str,
+    range: lsp::Range,
+) -> lsp::CompletionList {
+    let item = |option: &CommandOption| lsp::CompletionItem {
+        label: option.name.clone(),
+        kind: lsp::CompletionItemKind::Value,
+        edit: lsp::TextEdit { range, new_text: option.name.clone() },
+        insert_text_format: None,
+        sort_text: None,
+        filter_text: None,
+        detail: Some(option.signature.clone()),
+        documentation: (!option.description.is_empty())
+            .then(|| lsp::MarkupContent::plaintext(option.description.clone())),
+        data: None,
+    };
+    let candidates =
+        options.iter().map(|option| Candidate { tier: Tier::Other, item: item(option) }).collect();
+    rank(word, candidates)
+}
+
+/// Replace the tab stops and placeholders of a snippet with their default text.
+fn snippet_preview(body: &str) -> String {
+    let mut preview = String::with_capacity(body.len());
+    let mut chars = body.chars().peekable();
+    let tab_stop = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        std::iter::from_fn(|| chars.next_if(char::is_ascii_digit)).collect::<String>()
+    };
+    let mut placeholders: Vec<(String, usize)> = Vec::new();
+    let mut defaults: HashMap<String, String> = HashMap::new();
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => preview.extend(chars.next()),
+            '$' if chars.next_if_eq(&'{').is_some() => {
+                let number = tab_stop(&mut chars);
+                chars.next_if_eq(&':');
+                placeholders.push((number, preview.len()));
+            }
+            '$' if chars.peek().is_some_and(char::is_ascii_digit) => {
+                let number = tab_stop(&mut chars);
+                preview.push_str(defaults.get(&number).map_or("", String::as_str));
+            }
+            '}' => match placeholders.pop() {
+                Some((number, start)) => _ = defaults.insert(number, preview[start..].to_owned()),
+                None => preview.push('}'),
+            },
+            char => preview.push(char),
+        }
+    }
+    preview
+}
+
+fn snippet_candidates(shell: Shell, range: lsp::Range) -> impl Iterator<Item = Candidate> {
+    (SNIPPETS.iter().filter(move |snippet| snippet.shells.contains(&shell))).map(move |snippet| {
+        let item = lsp::CompletionItem {
+            label: String::from(snippet.label),
+            kind: lsp::CompletionItemKind::Snippet,
+            edit: lsp::TextEdit { range, new_text: String::from(snippet.body) },
+            insert_text_format: Some(lsp::InsertTextFormat::Snippet),
+            sort_text: None,
+            filter_text: None,
+            detail: Some(String::from(snippet.detail)),
+            documentation: Some(lsp::MarkupContent::markdown(format!(
+                "```sh\n{}\n```",
+                snippet_preview(snippet.body)
+            ))),
+            data: None,
+        };
+        Candidate { tier: Tier::Snippet, item }
+    })
+}
+
+/// Complete the expansion operators supported by `shell` that begin with `prefix`. They are listed
+/// in the order of `EXPANSION_OPERATORS`, which groups related operators.
+fn expansion_operator_completions(
+    shell: Shell,
+    prefix: &str,
+    range: lsp::Range,
+) -> lsp::CompletionList {
+    let items = (EXPANSION_OPERATORS.iter())
+        .filter(|operator| operator.shells.contains(&shell))
+        .filter(|operator| operator.operator.starts_with(prefix))
+        .enumerate()
+        .map(|(index, operator)| lsp::CompletionItem {
+            sort_text: Some(format!("{index:03}")),
+            filter_text: Some(String::from(operator.operator)),
+            detail: Some(String::from(operator.form)),
+            documentation: Some(lsp::MarkupContent::markdown(String::from(operator.description))),
+            ..plain_item(operator.operator, lsp::CompletionItemKind::Operator, range)
+        })
+        .collect();
+    lsp::CompletionList { is_incomplete: false, items }
+}
+
+/// Append the items of `second` to those of `first`, keeping both in their ranked order.
+fn concatenate(mut first: lsp::CompletionList, second: lsp::CompletionList) -> lsp::CompletionList {
+    let offset = first.items.len();
+    for (index, mut item) in second.items.into_iter().enumerate() {
+        item.sort_text = Some(format!("{:03}", offset + index));
+        first.items.push(item);
+    }
+    first.is_incomplete |= second.is_incomplete;
+    first
+}
+
+fn plain_item(
+    label: &str,
+    kind: lsp::CompletionItemKind,
+    range: lsp::Range,
+) -> lsp::CompletionItem {
+    lsp::CompletionItem {
+        label: String::from(label),
+        kind,
+        edit: lsp::TextEdit { range, new_text: String::from(label) },
+        insert_text_format: None,
+        sort_text: None,
+        filter_text: None,
+        detail: None,
+        documentation: None,
+        data: None,
+    }
+}
+
+fn keyword_candidates(keywords: &[&str], range: lsp::Range) -> Vec<Candidate> {
+    (keywords.iter())
+        .map(|&keyword| {
+            let item = lsp::CompletionItem {
+                detail: Some(String::from("Reserved word")),
+                documentation: syntax::find(keyword).map(|syntax| {
+                    lsp::MarkupContent::markdown(String::from(syntax.description))
+                }),
+                ..plain_item(keyword, lsp::CompletionItemKind::Keyword, range)
+            };
+            Candidate { tier: Tier::Keyword, item }
+        })
+        .collect()
+}
+
+fn documented_candidates(
+    entries: &[(&str, &str)],
+    kind: lsp::CompletionItemKind,
+    range: lsp::Range,
+) -> Vec<Candidate> {
+    (entries.iter())
+        .map(|&(label, description)| Candidate {
+            tier: Tier::Other,
+            item: lsp::CompletionItem {
+                documentation: Some(lsp::MarkupContent::markdown(String::from(description))),
+                ..plain_item(label, kind, range)
+            },
+        })
+        .collect()
+}
+
+/// Complete the Shellcheck codes reported in `document`.
+fn shellcheck_code_candidates(document: &db::Document, range: lsp::Range) -> Vec<Candidate> {
+    let mut codes: BTreeMap<i32, &str> = BTreeMap::new();
+    for diagnostic in document.info.diagnostics.iter().filter(|d| d.source == "shellcheck") {
+        codes.entry(diagnostic.code).or_insert(&diagnostic.message);
+    }
+    let item = |label: &str, detail: &str| lsp::CompletionItem {
+        detail: Some(String::from(detail)),
+        ..plain_item(label, lsp::CompletionItemKind::Value, range)
+    };
+    (codes.into_iter())
+        .map(|(code, message)| item(&format!("SC{code}"), message))
+        .chain(std::iter::once(item("all", "Disable all checks")))
+        .map(|item| Candidate { tier: Tier::Other, item })
+        .collect()
+}
+
+/// Complete annotation directives after `##@`, and directives in `# shellcheck` comments.
+/// `comment` is the part of the comment before the cursor, and `character` is where it starts.
+fn comment_completions(
+    document: &db::Document,
+    comment: &str,
+    character: u32,
+    cursor: lsp::Position,
+    directory: &Path,
+) -> lsp::CompletionList {
+    let range_from = |start: usize| lsp::Range {
+        start: lsp::Position {
+            line: cursor.line,
+            character: character + comment[..start].chars().count() as u32,
+        },
+        end: cursor,
+    };
+
+    if let Some(rest) = comment.strip_prefix("##@") {
+        let word = rest.trim_start();
+        if word.contains(char::is_whitespace) {
+            return lsp::CompletionList::default();
+        }
+        let range = range_from(comment.len() - word.len());
+        let kind = lsp::CompletionItemKind::Keyword;
+        return rank(word, documented_candidates(parse::ANNOTATION_DIRECTIVES, kind, range));
+    }
+
+    let Some(directives) = (comment.strip_prefix('#').map(str::trim_start))
+        .and_then(|comment| comment.strip_prefix("shellcheck"))
+        .filter(|directives| directives.starts_with(char::is_whitespace))
+    else {
+        return lsp::CompletionList::default();
+    };
+    let word = directives.rsplit(char::is_whitespace).next().unwrap_or("");
+    let word_start = comment.len() - word.len();
+
+    let Some((key, value)) = word.split_once('=') else {
+        let range = range_from(word_start);
+        let candidates = (shellcheck::DIRECTIVE_KEYS.iter())
+            .map(|&(key, description)| Candidate {
+                tier: Tier::Other,
+                item: lsp::CompletionItem {
+                    edit: lsp::TextEdit { range, new_text: format!("{key}=") },
+                    documentation: Some(lsp::MarkupContent::markdown(String::from(description))),
+                    ..plain_item(key, lsp::CompletionItemKind::Keyword, range)
+                },
+            })
+            .collect();
+        return rank(word, candidates);
+    };
+
+    let value_start = word_start + key.len() + 1;
+    let item_start = value_start + value.rfind(',').map_or(0, |index| index + 1);
+    let (query, range) = (&comment[item_start..], range_from(item_start));
+    let candidates = match key {
+        "disable" => shellcheck_code_candidates(document, range),
+        "enable" => {
+            let kind = lsp::CompletionItemKind::Value;
+            let mut candidates = documented_candidates(shellcheck::OPTIONAL_CHECKS, kind, range);
+            candidates.push(Candidate { tier: Tier::Other, item: plain_item("all", kind, range) });
+            candidates
+        }
+        "shell" => (shellcheck::SHELLS.iter())
+            .map(|shell| plain_item(shell, lsp::CompletionItemKind::Value, range))
+            .map(|item| Candidate { tier: Tier::Other, item })
+            .collect(),
+        "source" | "source-path" => {
+            return path_completions(value, range_from(value_start), directory);
+        }
+        _ => Vec::new(),
+    };
+    rank(query, candidates)
+}
+
+/// Short description of a symbol, shown next to its completion label.
+/// Command paths are looked up when the item is resolved.
+fn symbol_detail(info: &db::DocumentInfo, symbol: &db::Symbol) -> &'static str {
+    match symbol.kind {
+        db::SymbolKind::Variable(id) => info.variables[id].kind.describe(),
+        db::SymbolKind::Function(_) => "Function",
+        db::SymbolKind::Builtin => "Shell builtin",
+        db::SymbolKind::Command => "Command",
+        db::SymbolKind::Parameter(_) => "Parameter",
+        db::SymbolKind::Special(_) => "Special parameter",
+        db::SymbolKind::Error => "Error",
+    }
+}
+
+fn symbol_completion(
+    uri: &lsp::DocumentURI,
+    info: &db::DocumentInfo,
+    id: db::SymbolId,
+    range: lsp::Range,
+    kind: lsp::CompletionItemKind,
+) -> lsp::CompletionItem {
+    let symbol = &info.symbols[id];
+    lsp::CompletionItem {
+        label: symbol.name.clone(),
+        kind,
+        edit: lsp::TextEdit { range, new_text: symbol.name.clone() },
+        insert_text_format: None,
+        sort_text: None,
+        filter_text: None,
+        detail: Some(String::from(symbol_detail(info, symbol))),
+        documentation: None,
+        data: Some(lsp::CompletionData {
+            uri: uri.clone(),
+            symbol: id.get() as u32,
+            name: symbol.name.clone(),
+        }),
+    }
+}
+
+/// The range of the innermost function definition that contains `position`.
+fn enclosing_function(info: &db::DocumentInfo, position: lsp::Position) -> Option<lsp::Range> {
+    (info.functions.underlying.iter())
+        .filter_map(|function| function.definition.map(|definition| definition.range))
+        .filter(|range| range.contains(position))
+        .max_by_key(|range| range.start)
+}
+
+/// Locals rank above globals only within the function that declares them, which includes the
+/// functions nested in it.
+fn symbol_tier(info: &db::DocumentInfo, symbol: &db::Symbol, cursor: lsp::Position) -> Tier {
+    match symbol.kind {
+        db::SymbolKind::Variable(id) => match info.variables[id].kind {
+            db::VariableKind::Local => {
+                let declaration = info.variables[id].first_assignment;
+                let function = declaration
+                    .and_then(|declaration| enclosing_function(info, declaration.range.start));
+                if function.is_some_and(|function| function.contains(cursor)) {
+                    Tier::Local
+                }
+                else {
+                    Tier::Global
+                }
+            }
+            db::VariableKind::Global => Tier::Global,
+            db::VariableKind::Environment => Tier::Environment,
+        },
+        db::SymbolKind::Function(_) => Tier::Function,
+        db::SymbolKind::Builtin => Tier::Builtin,
+        db::SymbolKind::Command => Tier::Executable,
+        _ => Tier::Other,
+    }
+}
+
+fn symbol_candidates(
+    uri: &lsp::DocumentURI,
+    info: &db::DocumentInfo,
+    range: lsp::Range,
+    kind: lsp::CompletionItemKind,
+    predicate: impl Fn(&db::Symbol) -> bool,
+) -> Vec<Candidate> {
+    // The partially typed word is itself parsed as a reference, and should not complete itself.
+    let is_typed = |symbol: &db::Symbol| {
+        let at_cursor =
+            |&index: &u32| info.references[index as usize].reference.range.end == range.end;
+        !symbol.ref_indices.is_empty() && symbol.ref_indices.iter().all(at_cursor)
+    };
+    (info.symbols.underlying.iter().enumerate())
+        .filter(|(_, symbol)| predicate(symbol) && !is_typed(symbol))
+        .map(|(index, symbol)| Candidate {
+            tier: symbol_tier(info, symbol, range.start),
+            item: symbol_completion(uri, info, db::SymbolId::new(index), range, kind),
+        })
+        .collect()
+}
+
+/// Collect completion items for the cursor position on `line`. What is completed depends on the
+/// parse state of the document up to the current word. Documentation for symbols is left out; it
+/// is filled in on demand by `completionItem/resolve`. The options of a command are looked up with
+/// `options` when an option is being completed. Snippets are offered in command position if
+/// `snippets` is set.
+pub fn completions(
+    document: &db::Document,
+    uri: &lsp::DocumentURI,
+    line: &str,
+    cursor: lsp::Position,
+    snippets: bool,
+    options: &mut dyn FnMut(&str) -> Rc<[CommandOption]>,
+) -> lsp::CompletionList {
+    // The cursor counts characters, while the offsets into the line below are in bytes.
+    let prefix_end = (line.char_indices().nth(cursor.character as usize))
+        .map_or(line.len(), |(index, _)| index);
+    let line_prefix = &line[..prefix_end];
+    let word = current_word(line_prefix);
+    let text = &line_prefix[word.start..];
+    let character = |offset: usize| line_prefix[..offset].chars().count() as u32;
+    let directory = uri.path.parent().unwrap_or(Path::new("/"));
+    if word.is_comment {
+        return comment_completions(document, text, character(word.start), cursor, directory);
+    }
+    let range_from = |offset: usize| lsp::Range {
+        start: lsp::Position { line: cursor.line, character: character(word.start + offset) },
+        end: cursor,
+    };
+    let range = range_from(0);
+
+    let variables = |start: usize| {
+        let kind = lsp::CompletionItemKind::Variable;
+        let candidates = symbol_candidates(uri, &document.info, range_from(start), kind, |symbol| {
+            matches!(symbol.kind, db::SymbolKind::Variable(_))
+        });
+        rank(&text[start..], candidates)
+    };
+
+    if let Some((name_start, operator_start)) = expansion_operator_start(text) {
+        let (shell, range) = (document.info.shell, range_from(operator_start));
+        let operators = expansion_operator_completions(shell, &text[operator_start..], range);
+        if operator_start < text.len() || parameter_start(text).is_none() {
+            return operators;
+        }
+        // The name may be complete, or the beginning of a longer name.
+        let name = &text[name_start..operator_start];
+        let mut variables = variables(name_start);
+        let known = variables.items.iter().position(|item| item.label == name);
+        return match known {
+            Some(index) => {
+                variables.items.remove(index);
+                concatenate(operators, variables)
+            }
+            None => concatenate(variables, operators),
+        };
+    }
+    if let Some(start) = parameter_start(text) {
+        return variables(start);
+    }
+
+    let line_start = db::text_range(&document.text, lsp::Range::for_position(lsp::Position {
+        line: cursor.line,
+        character: 0,
+    }))
+    .start;
+    let input = &document.text[..line_start + word.start];
+    let context = parse::completion_context(input, document.info.shell);
+
+    let mut candidates = match context.position {
+        Some(CompletionPosition::Command) if text.contains('/') => {
+            return path_completions(text, range, directory);
+        }
+        Some(CompletionPosition::Command) => match text.split_once('=') {
+            Some((name, value)) if lex::is_name(name) => {
+                return path_completions(value, range_from(name.len() + 1), directory);
+            }
+            _ => {
+                let kind = lsp::CompletionItemKind::Function;
+                let mut candidates = symbol_candidates(uri, &document.info, range, kind, |symbol| {
+                    matches!(
+                        symbol.kind,
+                        db::SymbolKind::Command
+                            | db::SymbolKind::Builtin
+                            | db::SymbolKind::Function(_)
+                    )
+                });
+                if snippets {
+                    candidates.extend(snippet_candidates(document.info.shell, range));
+                }
+                candidates
+            }
+        },
+        Some(CompletionPosition::Argument { command: Some(command) }) if text.starts_with('-') => {
+            return option_completions(&options(&command), text, range);
+        }
+        Some(CompletionPosition::Argument { .. } | CompletionPosition::Redirect) => {
+            return path_completions(text, range, directory);
+        }
+        Some(CompletionPosition::VariableName) => {
+            let kind = lsp::CompletionItemKind::Variable;
+            symbol_candidates(uri, &document.info, range, kind, |symbol| {
+                matches!(symbol.kind, db::SymbolKind::Variable(_))
+            })
+        }
+        Some(CompletionPosition::FunctionName) => {
+            let kind = lsp::CompletionItemKind::Function;
+            symbol_candidates(uri, &document.info, range, kind, |symbol| {
+                matches!(symbol.kind, db::SymbolKind::Function(_))
+            })
+        }
+        Some(CompletionPosition::HeredocDelimiter | CompletionPosition::CasePattern) | None => {
+            Vec::new()
+        }
+    };
+    candidates.extend(keyword_candidates(&context.keywords, range));
+    rank(text, candidates)
+}
+
+/// Find the symbol a completion item was created for, if the document has not changed since.
+pub fn resolve_symbol<'a>(
+    info: &'a db::DocumentInfo,
+    data: &lsp::CompletionData,
+) -> Option<&'a db::Symbol> {
+    let symbol = info.symbols.get(db::SymbolId::new(data.symbol as usize))?;
+    (symbol.name == data.name).then_some(symbol)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    fn current_word(prefix: &str) -> (&str, bool) {
+        let word = super::current_word(prefix);
+        (&prefix[word.start..], word.is_comment)
+    }
+
+    #[test]
+    fn current_word_start() {
+        assert_eq!(current_word("ec"), ("ec", false));
+        assert_eq!(current_word("echo a"), ("a", false));
+        assert_eq!(current_word("echo "), ("", false));
+        assert_eq!(current_word("a && b"), ("b", false));
+        assert_eq!(current_word("echo 'a b"), ("'a b", false));
+        assert_eq!(current_word("echo a\\ b"), ("a\\ b", false));
+        assert_eq!(current_word("echo >f"), ("f", false));
+        assert_eq!(current_word("echo >&2 x"), ("x", false));
+        assert_eq!(current_word("cat <<-EO"), ("EO", false));
+        assert_eq!(current_word("$(ca"), ("ca", false));
+        assert_eq!(current_word("echo # a b"), ("# a b", true));
+        assert_eq!(current_word("echo a#b"), ("a#b", false));
+    }
+
+    #[test]
+    fn parameter_start() {
+        assert_eq!(super::parameter_start("$HO"), Some(1));
+        assert_eq!(super::parameter_start("\"a${b"), Some(4));
+        assert_eq!(super::parameter_start("$"), Some(1));
+        assert_eq!(super::parameter_start("a/b"), None);
+        assert_eq!(super::parameter_start("$a/b"), None);
+    }
+
+    #[test]
+    fn expansion_operator_start() {
+        assert_eq!(super::expansion_operator_start("${HOME"), Some((2, 6)));
+        assert_eq!(super::expansion_operator_start("\"${x:"), Some((3, 4)));
+        assert_eq!(super::expansion_operator_start("${1%"), Some((2, 3)));
+        assert_eq!(super::expansion_operator_start("${@"), Some((2, 3)));
+        assert_eq!(super::expansion_operator_start("${x:-a"), None);
+        assert_eq!(super::expansion_operator_start("${x}"), None);
+        assert_eq!(super::expansion_operator_start("${"), None);
+        assert_eq!(super::expansion_operator_start("$x"), None);
+    }
+
+    #[test]
+    fn fuzzy_score() {
+        assert_eq!(super::fuzzy_score("", "abc"), Some(0));
+        assert_eq!(super::fuzzy_score("ac", "abc"), Some(7));
+        assert_eq!(super::fuzzy_score("ca", "abc"), None);
+        assert!(super::fuzzy_score("ab", "abc") > super::fuzzy_score("ab", "axb"));
+        assert!(super::fuzzy_score("fb", "foo_bar") > super::fuzzy_score("fb", "fooxbar"));
+        assert!(super::fuzzy_score("Ab", "Abc") > super::fuzzy_score("Ab", "abc"));
+    }
+
+    #[test]
+    fn snippet_preview() {
+        let preview = super::snippet_preview("if ${1:condition}; then\n\t$0\nfi");
+        assert_eq!(preview, "if condition; then\n\t\nfi");
+        assert_eq!(super::snippet_preview("case ${1:\\$word} in"), "case $word in");
+        assert_eq!(super::snippet_preview("trap $1 EXIT \\$((x))"), "trap  EXIT $((x))");
+        assert_eq!(super::snippet_preview("${1:f}() {}\ntrap $1 EXIT"), "f() {}\ntrap f EXIT");
+        assert_eq!(super::snippet_preview("${1:f}() {\n\t$0\n}"), "f() {\n\t\n}");
+    }
+
+    #[test]
+    fn shellcheck_directives() {
+        let mut document = crate::db::Document::new("");
+        let diagnostic = |code| crate::lsp::Diagnostic {
+            range: crate::lsp::Range::default(),
+            severity: crate::lsp::Severity::Warning,
+            source: "shellcheck",
+            message: String::from("message"),
+            code,
+            code_description: None,
+            related: Vec::new(),
+        };
+        document.info.diagnostics.extend([diagnostic(2086), diagnostic(2034), diagnostic(2086)]);
+
+        let labels = |comment: &str| {
+            let cursor = crate::lsp::Position { line: 0, character: comment.len() as u32 };
+            let list = super::comment_completions(&document, comment, 0, cursor, Path::new("/"));
+            list.items.into_iter().map(|item| item.label).collect::<Vec<_>>()
+        };
+        assert_eq!(labels("# shellcheck disable=SC2034,"), ["all", "SC2034", "SC2086"]);
+        assert_eq!(labels("# shellcheck disable=SC20"), ["SC2034", "SC2086"]);
+        assert_eq!(labels("# shellcheck sh"), ["shell", "source-path"]);
+        assert_eq!(labels("##@ d"), ["desc"]);
+        assert_eq!(labels("##@ desc "), Vec::<String>::new());
+        assert_eq!(labels("# a comment"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn non_ascii_line() {
+        let text = "été=1\necho é $é";
+        let mut document = crate::db::Document::new(text);
+        document.info = crate::parse::parse(text, &crate::config::Settings::default());
+        let uri = crate::lsp::DocumentURI { path: "/a.sh".into() };
+        let complete = |character| {
+            let cursor = crate::lsp::Position { line: 1, character };
+            let mut options = |_: &str| std::rc::Rc::from(Vec::new());
+            let line = "echo é $é";
+            let list = super::completions(&document, &uri, line, cursor, false, &mut options);
+            (list.items.into_iter())
+                .map(|item| (item.label, item.edit.range.start.character))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(complete(9), [(String::from("été"), 8)]);
+        assert!(complete(6).iter().all(|(_, start)| *start == 5));
+    }
+
+    #[test]
+    fn local_tier() {
+        let text = "#!/bin/bash\nf() {\n\tlocal a\n\tg() { local c; }\n}\n\
+                    h() {\n\tlocal b\n\t:\n}\n";
+        let info = crate::parse::parse(text, &crate::config::Settings::default());
+        let tier = |name: &str, line| {
+            let symbol = info.symbols.underlying.iter().find(|symbol| symbol.name == name);
+            let cursor = crate::lsp::Position { line, character: 1 };
+            super::symbol_tier(&info, symbol.unwrap(), cursor)
+        };
+        assert_eq!(tier("a", 2), super::Tier::Local);
+        assert_eq!(tier("a", 3), super::Tier::Local);
+        assert_eq!(tier("a", 7), super::Tier::Global);
+        assert_eq!(tier("b", 7), super::Tier::Local);
+        assert_eq!(tier("b", 2), super::Tier::Global);
+        assert_eq!(tier("c", 2), super::Tier::Global);
+    }
+}
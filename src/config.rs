@@ -6,6 +6,7 @@ pub struct Shellcheck {
     pub enable: bool,
     pub posix_fallback: bool,
     pub arguments: Vec<String>,
+    pub unopened_documents: bool,
 }
 
 #[derive(serde::Deserialize)]
@@ -17,9 +18,10 @@ pub struct Shfmt {
 }
 
 #[derive(serde::Deserialize)]
-#[serde(default, deny_unknown_fields)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
 pub struct Help {
     pub enable: bool,
+    pub command_help: bool,
 }
 
 #[derive(serde::Deserialize)]
@@ -46,11 +48,20 @@ pub struct Environment {
     pub executables: bool,
 }
 
+#[derive(serde::Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct InlayHints {
+    pub argument_names: bool,
+    pub command_paths: bool,
+    pub sourced_files: bool,
+}
+
 #[derive(Default, serde::Deserialize)]
 #[serde(default, rename_all = "camelCase", deny_unknown_fields)]
 pub struct Settings {
     pub integrate: Integrate,
     pub environment: Environment,
+    pub inlay_hints: InlayHints,
     #[serde(deserialize_with = "deserialize_shell")]
     pub default_shell: Shell,
 }
@@ -63,7 +74,12 @@ pub struct Cmdline {
 
 impl Default for Shellcheck {
     fn default() -> Self {
-        Self { enable: true, posix_fallback: true, arguments: Vec::new() }
+        Self {
+            enable: true,
+            posix_fallback: true,
+            arguments: Vec::new(),
+            unopened_documents: false,
+        }
     }
 }
 
@@ -75,7 +91,7 @@ impl Default for Shfmt {
 
 impl Default for Help {
     fn default() -> Self {
-        Self { enable: true }
+        Self { enable: true, command_help: false }
     }
 }
 
@@ -91,6 +107,12 @@ impl Default for Environment {
     }
 }
 
+impl Default for InlayHints {
+    fn default() -> Self {
+        Self { argument_names: true, command_paths: false, sourced_files: false }
+    }
+}
+
 struct ShellVisitor;
 
 impl serde::de::Visitor<'_> for ShellVisitor {
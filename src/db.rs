@@ -1,6 +1,6 @@
 use crate::indexvec::IndexVec;
 use crate::shell::Shell;
-use crate::{define_index, lsp};
+use crate::{define_index, lsp, paths};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
@@ -31,7 +31,9 @@ pub enum VariableKind {
 pub struct Variable {
     pub description: Option<String>,
     pub first_assignment: Option<Location>,
+    pub value: Option<Location>,
     pub kind: VariableKind,
+    pub readonly: bool,
 }
 
 pub struct Function {
@@ -72,10 +74,28 @@ pub struct Symbol {
     pub ref_indices: Vec<u32>,
 }
 
+/// How the text around a reference is quoted, which determines how text that replaces it must be
+/// escaped.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Quoting {
+    #[default]
+    Unquoted,
+    DoubleQuotes,
+    HereDocument,
+}
+
 #[derive(Clone, Copy)]
 pub struct SymbolReference {
     pub reference: lsp::Reference,
     pub id: SymbolId,
+    pub quoting: Quoting,
+}
+
+/// A simple command invocation along with its arguments.
+pub struct Call {
+    pub name: Location,
+    pub id: SymbolId,
+    pub arguments: Vec<Location>,
 }
 
 pub enum ActionKind {
@@ -83,6 +103,12 @@ pub enum ActionKind {
         title: String,
         edits: Vec<lsp::TextEdit>,
     },
+    /// A fix for a diagnostic with a Shellcheck code, whether reported by Shellcheck or natively.
+    Fix {
+        code: i32,
+        title: String,
+        edits: Vec<lsp::TextEdit>,
+    },
     DisableShellcheck {
         code: i32,
     },
@@ -93,6 +119,32 @@ pub struct Action {
     pub range: lsp::Range,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BashismKind {
+    Array,           // name=(a b)
+    DoubleBracket,   // [[ test ]]
+    Local,           // local name
+    FunctionKeyword, // function name
+    AnsiCString,     // $'text'
+    HereString,      // <<< word
+}
+
+/// A construct supported by Bash but not specified by POSIX.
+pub struct Bashism {
+    pub kind: BashismKind,
+    pub range: lsp::Range,
+}
+
+/// A `# shellcheck disable=` directive, along with the part of the document it applies to.
+pub struct Suppression {
+    /// The range of the directive comment.
+    pub directive: lsp::Range,
+    /// The disabled code ranges, which are single codes unless written as `SC2000-SC2099`, along
+    /// with where they are written in the directive.
+    pub codes: Vec<(std::ops::RangeInclusive<i32>, lsp::Range)>,
+    pub scope: lsp::Range,
+}
+
 #[derive(Default)]
 pub struct DocumentInfo {
     pub script_parameters: Option<Vec<Location>>,
@@ -101,21 +153,40 @@ pub struct DocumentInfo {
     pub functions: IndexVec<Function, FunctionId>,
     pub variables: IndexVec<Variable, VariableId>,
     pub symbols: IndexVec<Symbol, SymbolId>,
+    pub calls: Vec<Call>,
+    /// The start positions of the top-level statements.
+    pub statements: Vec<lsp::Position>,
+    /// The start positions of all statements, including nested ones. Commands after `&&`, `||`,
+    /// or `|` continue the statement before them and are not included.
+    pub statement_starts: Vec<lsp::Position>,
+    /// The lines of here-document bodies, including their closing delimiters.
+    pub heredocs: Vec<lsp::Range>,
     pub actions: Vec<Action>,
+    pub bashisms: Vec<Bashism>,
+    pub suppressions: Vec<Suppression>,
     pub tokens: lsp::SemanticTokensData,
     pub shell: Shell,
 }
 
+/// Encoded semantic tokens that were sent to the client, used to compute deltas.
+pub struct SentTokens {
+    pub result_id: String,
+    pub data: Vec<u32>,
+}
+
 #[derive(Default)]
 pub struct Document {
     pub text: String,
     pub info: DocumentInfo,
+    pub revision: u32,
+    pub sent_tokens: Option<SentTokens>,
 }
 
 #[derive(Default)]
 pub struct Database {
     pub documents: IndexVec<Document, DocumentId>,
     pub document_paths: HashMap<PathBuf, DocumentId>,
+    pub revision: u32,
 }
 
 pub fn text_range(text: &str, range: lsp::Range) -> std::ops::Range<usize> {
@@ -152,7 +223,31 @@ impl Database {
         self.document_paths.insert(path, self.documents.push(document));
     }
     pub fn close(&mut self, path: &Path) {
-        self.documents[self.document_paths[path]] = Document::default();
+        if let Some(id) = self.document_paths.remove(path) {
+            self.documents[id] = Document::default();
+        }
+    }
+    /// Move the documents at or below `old` to `new`. A moved document replaces any document
+    /// that was open at its new path, since the rename overwrote that file.
+    pub fn rename(&mut self, old: &Path, new: &Path) {
+        let moved: Vec<(PathBuf, DocumentId)> = (self.document_paths.iter())
+            .filter(|(path, _)| path.starts_with(old))
+            .map(|(path, &id)| (path.clone(), id))
+            .collect();
+        for (path, _) in &moved {
+            self.document_paths.remove(path);
+        }
+        for (path, id) in moved {
+            let path = paths::renamed(&path, old, new).unwrap_or(path);
+            if let Some(replaced) = self.document_paths.insert(path, id) {
+                self.documents[replaced] = Document::default();
+            }
+        }
+    }
+    /// Get a new revision number, which is unique among all documents.
+    pub fn next_revision(&mut self) -> u32 {
+        self.revision += 1;
+        self.revision
     }
 }
 
@@ -173,7 +268,7 @@ impl DocumentInfo {
 
 impl Document {
     pub fn new(text: impl Into<String>) -> Self {
-        Self { text: text.into(), info: DocumentInfo::default() }
+        Self { text: text.into(), ..Self::default() }
     }
     pub fn edit(&mut self, range: lsp::Range, new_text: &str) {
         self.text.replace_range(text_range(&self.text, range), new_text);
@@ -188,16 +283,41 @@ impl Symbol {
 
 impl SymbolReference {
     pub fn read(range: lsp::Range, id: SymbolId) -> Self {
-        Self { reference: lsp::Reference { range, kind: lsp::ReferenceKind::Read }, id }
+        let reference = lsp::Reference { range, kind: lsp::ReferenceKind::Read };
+        Self { reference, id, quoting: Quoting::Unquoted }
     }
     pub fn write(range: lsp::Range, id: SymbolId) -> Self {
-        Self { reference: lsp::Reference { range, kind: lsp::ReferenceKind::Write }, id }
+        let reference = lsp::Reference { range, kind: lsp::ReferenceKind::Write };
+        Self { reference, id, quoting: Quoting::Unquoted }
     }
 }
 
 impl Variable {
     pub fn new(kind: VariableKind) -> Self {
-        Self { description: None, first_assignment: None, kind }
+        Self { description: None, first_assignment: None, value: None, kind, readonly: false }
+    }
+}
+
+impl VariableKind {
+    pub fn describe(self) -> &'static str {
+        match self {
+            VariableKind::Global => "Variable",
+            VariableKind::Local => "Local variable",
+            VariableKind::Environment => "Environment variable",
+        }
+    }
+}
+
+impl BashismKind {
+    pub fn message(self) -> &'static str {
+        match self {
+            BashismKind::Array => "Arrays are not specified by POSIX",
+            BashismKind::DoubleBracket => "`[[` is not specified by POSIX",
+            BashismKind::Local => "`local` is not specified by POSIX",
+            BashismKind::FunctionKeyword => "The `function` keyword is not specified by POSIX",
+            BashismKind::AnsiCString => "`$'...'` strings are not specified by POSIX",
+            BashismKind::HereString => "Here-strings are not specified by POSIX",
+        }
     }
 }
 
@@ -210,6 +330,7 @@ impl View {
 #[cfg(test)]
 mod tests {
     use crate::lsp;
+    use std::path::Path;
 
     #[test]
     fn edit_document() {
@@ -229,4 +350,17 @@ mod tests {
         document.edit(range(pos(0, 5), pos(1, 0)), "\n\n");
         assert_eq!(document.text, "hello\n\n\nworld");
     }
+
+    #[test]
+    fn rename() {
+        let mut db = super::Database::default();
+        db.open("/a/x.sh".into(), super::Document::new("x"));
+        db.open("/a/y.sh".into(), super::Document::new("y"));
+        db.open("/b/x.sh".into(), super::Document::new("old"));
+        db.rename("/a".as_ref(), "/b".as_ref());
+        let text = |path: &str| &db.documents[db.document_paths[Path::new(path)]].text;
+        assert_eq!(db.document_paths.len(), 2);
+        assert_eq!(text("/b/x.sh"), "x");
+        assert_eq!(text("/b/y.sh"), "y");
+    }
 }
@@ -1,6 +1,11 @@
 use std::io::Read;
 use std::path::{Path, PathBuf};
 
+/// Stop searching for scripts in a workspace after finding this many.
+const MAX_WORKSPACE_SCRIPTS: usize = 10000;
+
+pub const SCRIPT_EXTENSIONS: &[&str] = &["sh", "bash", "dash", "ksh", "mksh", "zsh"];
+
 #[cfg(unix)]
 fn is_executable(data: std::fs::Metadata) -> bool {
     use std::os::unix::fs::PermissionsExt;
@@ -43,3 +48,59 @@ pub fn is_script(path: &Path) -> bool {
         file.read_exact(&mut buffer).is_ok() && buffer.as_slice() == b"#!/"
     })
 }
+
+fn has_shell_shebang(path: &Path) -> bool {
+    let mut buffer = [0u8; 64];
+    let Ok(length) = std::fs::File::open(path).and_then(|mut file| file.read(&mut buffer))
+    else {
+        return false;
+    };
+    let line = buffer[..length].split(|&byte| byte == b'\n').next().unwrap_or_default();
+    std::str::from_utf8(line).ok().and_then(|line| line.strip_prefix("#!")).is_some_and(|shebang| {
+        crate::shell::parse_shebang(shebang).is_ok()
+    })
+}
+
+/// Check whether `path` looks like a shell script, based on its extension or shebang.
+pub fn is_shell_script(path: &Path) -> bool {
+    match path.extension() {
+        Some(extension) => extension.to_str().is_some_and(|ext| SCRIPT_EXTENSIONS.contains(&ext)),
+        None => has_shell_shebang(path),
+    }
+}
+
+/// The entries of `directory` that are not hidden, in reverse order of their names.
+fn visible_entries(directory: &Path) -> Vec<(PathBuf, std::fs::FileType)> {
+    let mut entries: Vec<(PathBuf, std::fs::FileType)> = (std::fs::read_dir(directory).into_iter())
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_str().is_some_and(|name| !name.starts_with('.')))
+        .filter_map(|entry| Some((entry.path(), entry.file_type().ok()?)))
+        .collect();
+    entries.sort_unstable_by(|(a, _), (b, _)| b.cmp(a));
+    entries
+}
+
+/// Find shell scripts in `directory` and its subdirectories, skipping hidden entries. The scripts
+/// are found in sorted order, so the same scripts are returned when there are too many of them.
+pub fn workspace_scripts(directory: &Path) -> Vec<PathBuf> {
+    let mut scripts = Vec::new();
+    let mut stack = vec![visible_entries(directory)];
+    while let Some(entries) = stack.last_mut() {
+        let Some((path, kind)) = entries.pop()
+        else {
+            stack.pop();
+            continue;
+        };
+        if kind.is_dir() {
+            stack.push(visible_entries(&path));
+        }
+        else if kind.is_file() && is_shell_script(&path) {
+            scripts.push(path);
+            if scripts.len() == MAX_WORKSPACE_SCRIPTS {
+                break;
+            }
+        }
+    }
+    scripts
+}
@@ -1,6 +1,8 @@
 use crate::shell::Shell;
 use std::io::Write;
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
 fn zsh_help(name: &str, shell: &str) -> Option<String> {
     let mut child = Command::new(shell)
@@ -32,8 +34,56 @@ fn posix_help(name: &str, shell: &str) -> Option<String> {
 
 pub fn documentation(shell: Shell, name: &str) -> Option<String> {
     match shell {
-        Shell::Zsh => zsh_help(name, "zsh"),
-        Shell::Bash => posix_help(name, "bash"),
-        _ => posix_help(name, "sh"),
+        Shell::Zsh => zsh_help(name, executable(shell)),
+        _ => posix_help(name, executable(shell)),
     }
 }
+
+/// The shell that is asked for help on the builtins of `shell`.
+pub fn executable(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Zsh => "zsh",
+        Shell::Bash => "bash",
+        _ => "sh",
+    }
+}
+
+/// Commands that do not finish printing their help text within this time are killed.
+const COMMAND_HELP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Run `command --help` and capture its output.
+pub fn command_help(command: &str) -> Option<String> {
+    let mut child = Command::new(command)
+        .arg("--help")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    let Some(stdout) = child.stdout.take() else {
+        return kill(child);
+    };
+    // Read on another thread so that a full pipe can not block the command past the deadline.
+    // A background process may keep the pipe open, so the reader is abandoned on timeout.
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || _ = sender.send(std::io::read_to_string(stdout).ok()));
+
+    let deadline = Instant::now() + COMMAND_HELP_TIMEOUT;
+    let output = receiver.recv_timeout(COMMAND_HELP_TIMEOUT).ok().flatten();
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+            _ => return kill(child),
+        }
+    }
+    output.filter(|output| !output.is_empty())
+}
+
+/// Kill and reap `child` when its help text can not be used.
+fn kill(mut child: Child) -> Option<String> {
+    _ = child.kill();
+    _ = child.wait();
+    None
+}
@@ -17,3 +17,18 @@ pub fn documentation(shell: Shell, name: &str, config: &config::Man) -> Option<S
     let stdout = std::io::read_to_string(child.stdout.take().unwrap()).ok()?;
     child.wait().ok()?.success().then_some(stdout)
 }
+
+/// Extract the `NAME` and `SYNOPSIS` sections of a formatted manual page.
+pub fn synopsis(manual: &str) -> Option<String> {
+    let mut lines = Vec::new();
+    let mut include = false;
+    for line in manual.lines() {
+        if !line.is_empty() && !line.starts_with(char::is_whitespace) {
+            include = matches!(line.trim(), "NAME" | "SYNOPSIS");
+        }
+        if include {
+            lines.push(line);
+        }
+    }
+    (!lines.is_empty()).then(|| lines.join("\n").trim_end().to_owned())
+}
@@ -1,5 +1,6 @@
 pub mod help;
 pub mod man;
+pub mod options;
 pub mod shellcheck;
 pub mod shfmt;
 
@@ -0,0 +1,185 @@
+/// A command line option documented in a manual page or `--help` output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandOption {
+    /// The option itself, like `-a` or `--all`.
+    pub name: String,
+    /// The documented form of the option along with its aliases, like `-w, --width=COLS`.
+    pub signature: String,
+    pub description: String,
+}
+
+/// Remove backspace overstrikes used for bold and underlined text, and normalize dashes.
+fn normalize(text: &str) -> String {
+    let mut string = String::with_capacity(text.len());
+    for char in text.chars() {
+        match char {
+            '\x08' => _ = string.pop(),
+            '\u{2010}' | '\u{2212}' => string.push('-'),
+            char => string.push(char),
+        }
+    }
+    string
+}
+
+fn is_option_name(name: &str) -> bool {
+    let is_name_char = |char: char| char.is_ascii_alphanumeric() || "_-".contains(char);
+    match name.strip_prefix("--") {
+        Some(long) => long.starts_with(|char: char| char.is_ascii_alphanumeric())
+            && long.chars().all(is_name_char),
+        None => name.strip_prefix('-').is_some_and(|short| {
+            let mut chars = short.chars();
+            match (chars.next(), chars.as_str()) {
+                (Some(char), "") => char.is_ascii_graphic() && char != '-',
+                (Some(char), rest) => char.is_ascii_alphabetic() && rest.chars().all(is_name_char),
+                (None, _) => false,
+            }
+        }),
+    }
+}
+
+fn is_placeholder(word: &str) -> bool {
+    word.starts_with(['<', '[', '{'])
+        || word.chars().all(|char| char.is_ascii_uppercase() || "_-.,|".contains(char))
+}
+
+/// Split an option line into the option signature and the description that follows it,
+/// separated by at least two spaces or a tab.
+fn split_signature(line: &str) -> (&str, &str) {
+    let end = (line.find("  ").into_iter())
+        .chain(line.find('\t'))
+        .min()
+        .unwrap_or(line.len());
+    (line[..end].trim_end(), line[end..].trim())
+}
+
+fn option_names(signature: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for word in signature.split([',', ' ']).filter(|word| !word.is_empty()) {
+        if word.starts_with('-') {
+            let name = word.split(['=', '[', '<']).next().unwrap_or(word);
+            if is_option_name(name) {
+                names.push(String::from(name));
+            }
+        }
+        else if !is_placeholder(word) {
+            break;
+        }
+    }
+    names
+}
+
+/// Width of the leading whitespace of `line`, with tab stops every eight columns.
+fn indentation(line: &str) -> usize {
+    let mut width = 0;
+    for char in line.chars() {
+        match char {
+            ' ' => width += 1,
+            '\t' => width += 8 - width % 8,
+            _ => break,
+        }
+    }
+    width
+}
+
+fn starts_option(line: &str) -> bool {
+    line.starts_with('-') && !option_names(split_signature(line).0).is_empty()
+}
+
+/// Extract the options documented in a manual page or in `--help` output.
+pub fn parse(text: &str) -> Vec<CommandOption> {
+    let text = normalize(text);
+    let mut options: Vec<CommandOption> = Vec::new();
+    let mut lines = text.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        if !trimmed.starts_with('-') {
+            continue;
+        }
+        let indent = indentation(line);
+        let (signature, rest) = split_signature(trimmed);
+        let names = option_names(signature);
+        if names.is_empty() {
+            continue;
+        }
+
+        let mut description = String::from(rest);
+        while let Some(next) = lines.peek() {
+            let next_trimmed = next.trim_start();
+            if next_trimmed.is_empty() {
+                if !description.is_empty() {
+                    break;
+                }
+            }
+            else if indentation(next) > indent && !starts_option(next_trimmed) {
+                if !description.is_empty() {
+                    description.push(' ');
+                }
+                description.push_str(next_trimmed);
+            }
+            else {
+                break;
+            }
+            lines.next();
+        }
+
+        for name in names {
+            if !options.iter().any(|option| option.name == name) {
+                let signature = String::from(signature);
+                let description = description.clone();
+                options.push(CommandOption { name, signature, description });
+            }
+        }
+    }
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    fn names(text: &str) -> Vec<String> {
+        super::parse(text).into_iter().map(|option| option.name).collect()
+    }
+
+    #[test]
+    fn manual_page() {
+        let manual = "LS(1)\n\nNAME\n       ls - list directory contents\n\n\
+                      DESCRIPTION\n\
+                      \x20      -a, --all\n              do not ignore entries starting with .\n\n\
+                      \x20      -w, --width=COLS\n\
+                      \x20             set output width to COLS.  0 means\n\
+                      \x20             no limit\n";
+        let options = super::parse(manual);
+        assert_eq!(names(manual), ["-a", "--all", "-w", "--width"]);
+        assert_eq!(options[0].signature, "-a, --all");
+        assert_eq!(options[0].description, "do not ignore entries starting with .");
+        assert_eq!(options[3].description, "set output width to COLS.  0 means no limit");
+    }
+
+    #[test]
+    fn help_output() {
+        let help = "Usage: ls [OPTION]... [FILE]...\n\
+                    \x20 -A, --almost-all           do not list implied . and ..\n\
+                    \x20     --block-size=SIZE      with -l, scale sizes by SIZE\n\
+                    \x20                              before printing them\n\
+                    \x20 -1                         list one file per line\n\
+                    \x20 - not an option\n";
+        let options = super::parse(help);
+        assert_eq!(names(help), ["-A", "--almost-all", "--block-size", "-1"]);
+        assert_eq!(options[2].description, "with -l, scale sizes by SIZE before printing them");
+    }
+
+    #[test]
+    fn builtin_help() {
+        let help = "read: read [-ers] [-a array]\n    Options:\n      -a array\tassign the words\n\
+                    \t\tvariable ARRAY\n      -e\tuse Readline\n";
+        let options = super::parse(help);
+        assert_eq!(names(help), ["-a", "-e"]);
+        assert_eq!(options[0].signature, "-a array");
+        assert_eq!(options[0].description, "assign the words variable ARRAY");
+    }
+
+    #[test]
+    fn overstrike() {
+        assert_eq!(names("  -\x08--\x08-a\x08al\x08ll\x08l  all"), ["--all"]);
+    }
+}
@@ -84,6 +84,7 @@ fn diagnostic(comment: Comment) -> lsp::Diagnostic {
         source: "shellcheck",
         message: comment.message,
         code: comment.code,
+        code_description: Some(code_description(comment.code)),
         related: Vec::new(),
     }
 }
@@ -92,6 +93,150 @@ fn text_edit(replacement: Replacement) -> lsp::TextEdit {
     lsp::TextEdit { range: range(replacement.range), new_text: replacement.new_text }
 }
 
+// Generated by `build.rs` from `data/shellcheck-codes.tsv`.
+include!(concat!(env!("OUT_DIR"), "/shellcheck_codes.rs"));
+
+/// Get the title and rationale of a Shellcheck code.
+pub fn explanation(code: i32) -> Option<(&'static str, &'static str)> {
+    (EXPLANATIONS.binary_search_by_key(&code, |&(code, _, _)| code).ok())
+        .map(|index| (EXPLANATIONS[index].1, EXPLANATIONS[index].2))
+}
+
+pub fn wiki_url(code: i32) -> String {
+    format!("https://www.shellcheck.net/wiki/SC{code}")
+}
+
+pub fn code_description(code: i32) -> lsp::CodeDescription {
+    lsp::CodeDescription { href: wiki_url(code) }
+}
+
+/// Describe a Shellcheck code in markdown.
+pub fn explanation_markdown(code: i32) -> String {
+    let link = format!("[Shellcheck wiki]({})", wiki_url(code));
+    match explanation(code) {
+        Some((title, rationale)) => format!("**SC{code}**: {title}\n\n{rationale}\n\n{link}"),
+        None => format!("**SC{code}**\n\n{link}"),
+    }
+}
+
+/// Find the Shellcheck code under the cursor at `character` in a `# shellcheck` directive
+/// comment on `line`, along with the character range of the code.
+pub fn directive_code_at(line: &str, character: u32) -> Option<(i32, std::ops::Range<u32>)> {
+    let comment = line.trim_start().strip_prefix('#')?.trim_start();
+    if !comment.strip_prefix("shellcheck")?.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let chars: Vec<char> = line.chars().collect();
+    let cursor = character as usize;
+    let is_separator = |char: &char| char.is_whitespace() || matches!(char, ',' | '=');
+    let before = &chars[..cursor.min(chars.len())];
+    let start = before.iter().rposition(is_separator).map_or(0, |index| index + 1);
+    let after = chars[start..].iter().position(is_separator);
+    let end = after.map_or(chars.len(), |index| start + index);
+    if end <= cursor {
+        return None;
+    }
+    let code = chars[start..end].iter().collect::<String>().strip_prefix("SC")?.parse().ok()?;
+    Some((code, start as u32..end as u32))
+}
+
+fn is_disabled(suppression: &db::Suppression, diagnostic: &lsp::Diagnostic) -> bool {
+    suppression.scope.contains(diagnostic.range.start)
+        && suppression.codes.iter().any(|(codes, _)| codes.contains(&diagnostic.code))
+}
+
+/// Check whether `diagnostic` is disabled by a `# shellcheck disable=` directive.
+pub fn is_suppressed(suppressions: &[db::Suppression], diagnostic: &lsp::Diagnostic) -> bool {
+    diagnostic.code != 0
+        && suppressions.iter().any(|suppression| is_disabled(suppression, diagnostic))
+}
+
+/// Report the single codes disabled by directives that are not among `diagnostics` within the
+/// scope of the directive. Only codes accepted by `is_checked` are considered.
+pub fn unused_suppressions(
+    suppressions: &[db::Suppression],
+    diagnostics: &[lsp::Diagnostic],
+    is_checked: impl Fn(i32) -> bool,
+) -> Vec<lsp::Diagnostic> {
+    let mut unused = Vec::new();
+    for suppression in suppressions {
+        for (codes, range) in &suppression.codes {
+            let code = *codes.start();
+            let is_reported = |diagnostic: &lsp::Diagnostic| {
+                diagnostic.code == code && suppression.scope.contains(diagnostic.range.start)
+            };
+            let is_single = codes.start() == codes.end();
+            if is_single && is_checked(code) && !diagnostics.iter().any(is_reported) {
+                let message = format!("SC{code} is not reported where this directive applies");
+                unused.push(lsp::Diagnostic::new(*range, lsp::Severity::Hint, message));
+            }
+        }
+    }
+    unused
+}
+
+/// Blank out the `disable=` items of `# shellcheck` directives, so that Shellcheck reports what
+/// they disable. Other items, like `shell=` and `source=`, are kept, and directives without them
+/// are blanked entirely.
+pub fn without_suppressions(text: &str, suppressions: &[db::Suppression]) -> String {
+    let mut text = String::from(text);
+    for suppression in suppressions {
+        let range = db::text_range(&text, suppression.directive);
+        let directive = &text[range.clone()];
+        let mut is_kept = false;
+        let blanked: String = (directive.split_inclusive(char::is_whitespace))
+            .map(|word| {
+                let item = word.trim_end();
+                if item.starts_with("disable=") {
+                    " ".repeat(item.chars().count()) + &word[item.len()..]
+                }
+                else {
+                    is_kept |= item.contains('=');
+                    String::from(word)
+                }
+            })
+            .collect();
+        let replacement = if is_kept {
+            blanked
+        }
+        else {
+            format!("#{}", " ".repeat(directive.chars().count() - 1))
+        };
+        text.replace_range(range, &replacement);
+    }
+    text
+}
+
+/// Keys accepted by `# shellcheck` directive comments, along with their descriptions.
+pub const DIRECTIVE_KEYS: &[(&str, &str)] = &[
+    ("disable", "Disable the given comma-separated checks for the next command."),
+    ("enable", "Enable the given comma-separated optional checks."),
+    ("source", "Tell Shellcheck where to find the file sourced by the next command."),
+    (
+        "source-path",
+        "Add a directory to search for sourced files. `SCRIPTDIR` is the script's directory.",
+    ),
+    ("shell", "Specify the shell dialect of the script."),
+    ("external-sources", "Allow following sourced files that are not given on the command line."),
+];
+
+/// Optional checks that can be enabled with `# shellcheck enable=`.
+pub const OPTIONAL_CHECKS: &[(&str, &str)] = &[
+    ("add-default-case", "Suggest adding a default case in `case` statements"),
+    ("avoid-nullary-conditions", "Suggest explicitly using -n in `[ $var ]`"),
+    ("check-extra-masked-returns", "Check for additional cases where exit codes are masked"),
+    ("check-set-e-suppressed", "Notify when set -e is suppressed during function invocation"),
+    ("check-unassigned-uppercase", "Warn when uppercase variables are unassigned"),
+    ("deprecate-which", "Suggest 'command -v' instead of 'which'"),
+    ("quote-safe-variables", "Suggest quoting variables without metacharacters"),
+    ("require-double-brackets", "Require [[ and warn about [ in Bash/Ksh"),
+    ("require-variable-braces", "Suggest putting braces around all variable references"),
+    ("useless-use-of-cat", "Check for Useless Use Of Cat (UUOC)"),
+];
+
+/// Shell dialects accepted by `# shellcheck shell=`.
+pub const SHELLS: &[&str] = &["sh", "bash", "dash", "ksh", "busybox"];
+
 /// Identify diagnostics that are not helpful by themselves.
 fn is_context_diagnostic(code: i32) -> bool {
     matches!(code, 1009 | 1072 | 1073) // https://www.shellcheck.net/wiki
@@ -111,7 +256,8 @@ fn info(items: Vec<Item>) -> Info {
     for Item { comment, fix } in items {
         if let Some(fix) = fix {
             info.actions.push(db::Action {
-                kind: db::ActionKind::Edit {
+                kind: db::ActionKind::Fix {
+                    code: comment.code,
                     title: format!("SC{}: {}", comment.code, comment.message),
                     edits: fix.replacements.into_iter().map(text_edit).collect(),
                 },
@@ -139,10 +285,15 @@ fn shell_flag(shell: Shell, config: &config::Shellcheck) -> Option<&'static str>
     }
 }
 
-pub fn analyze(text: &str, shell: Shell, config: &config::Shellcheck) -> std::io::Result<Info> {
+/// Run Shellcheck on `text`. Returns `None` if Shellcheck does not support `shell`.
+pub fn analyze(
+    text: &str,
+    shell: Shell,
+    config: &config::Shellcheck,
+) -> std::io::Result<Option<Info>> {
     let Some(shell_flag) = shell_flag(shell, config)
     else {
-        return Ok(Info::default());
+        return Ok(None);
     };
 
     let mut child = Command::new("shellcheck")
@@ -156,5 +307,49 @@ pub fn analyze(text: &str, shell: Shell, config: &config::Shellcheck) -> std::io
     let items: Vec<Item> = serde_json::from_reader(child.stdout.take().unwrap())?;
 
     child.wait()?;
-    Ok(info(items))
+    Ok(Some(info(items)))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn explanation() {
+        assert!(super::EXPLANATIONS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        let (title, _) = super::explanation(2086).unwrap();
+        assert_eq!(title, "Double quote to prevent globbing and word splitting.");
+        assert!(super::explanation(1).is_none());
+    }
+
+    #[test]
+    fn suppressions() {
+        let text = "#!/bin/sh\n# shellcheck disable=SC2086,SC2006\necho $a\n";
+        let info = crate::parse::parse(text, &crate::config::Settings::default());
+        let suppressions = info.suppressions.as_slice();
+        let is_suppressed = |diagnostic| super::is_suppressed(suppressions, diagnostic);
+        assert!(info.diagnostics.iter().all(is_suppressed));
+        let unused = super::unused_suppressions(suppressions, &info.diagnostics, |_| true);
+        let messages: Vec<&str> = unused.iter().map(|diagnostic| &*diagnostic.message).collect();
+        assert_eq!(messages, ["SC2006 is not reported where this directive applies"]);
+        assert_eq!(
+            super::without_suppressions(text, &info.suppressions),
+            format!("#!/bin/sh\n#{}\necho $a\n", " ".repeat(33))
+        );
+        let text = "# shellcheck disable=SC2086 shell=sh\necho $a\n";
+        let info = crate::parse::parse(text, &crate::config::Settings::default());
+        assert_eq!(
+            super::without_suppressions(text, &info.suppressions),
+            format!("# shellcheck {} shell=sh\necho $a\n", " ".repeat(14))
+        );
+    }
+
+    #[test]
+    fn directive_code_at() {
+        let line = "  # shellcheck disable=SC2034,SC2086";
+        assert_eq!(super::directive_code_at(line, 23), Some((2034, 23..29)));
+        assert_eq!(super::directive_code_at(line, 35), Some((2086, 30..36)));
+        assert_eq!(super::directive_code_at(line, 29), None);
+        assert_eq!(super::directive_code_at(line, 36), None);
+        assert_eq!(super::directive_code_at("# shellcheckdisable=SC2034", 22), None);
+        assert_eq!(super::directive_code_at("echo SC2034", 6), None);
+    }
 }
@@ -46,6 +46,8 @@ pub struct Token {
 pub struct Lexer<'a> {
     chars: PosChars<'a>,
     next: Option<Token>,
+    previous: Option<Token>,
+    skip: Option<db::View>,
 }
 
 fn is_word(char: char) -> bool {
@@ -150,17 +152,42 @@ impl Iterator for Lexer<'_> {
     type Item = Token;
 
     fn next(&mut self) -> Option<Token> {
-        self.next.take().or_else(|| lex(&mut self.chars, next_token))
+        let token = self.next.take().or_else(|| self.lex());
+        self.previous = token.or(self.previous);
+        token
     }
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
-        Self { chars: PosChars::new(input), next: None }
+        Self { chars: PosChars::new(input), next: None, previous: None, skip: None }
+    }
+    /// Continue lexing `input` from `offset`, which corresponds to `position`.
+    pub fn resume(input: &'a str, position: lsp::Position, offset: u32) -> Self {
+        let chars = PosChars::resume(input, position, offset);
+        Self { chars, next: None, previous: None, skip: None }
+    }
+    fn lex(&mut self) -> Option<Token> {
+        if let Some(skip) = self.skip.filter(|skip| skip.start <= self.chars.offset) {
+            while self.chars.offset < skip.end && self.chars.next().is_some() {}
+            self.skip = None;
+        }
+        lex(&mut self.chars, next_token)
+    }
+    /// Skip over `region` once the lexer reaches it. Used for here-document bodies.
+    pub fn skip_region(&mut self, region: db::View) {
+        self.skip = Some(region);
+    }
+    /// The region that will be skipped, if any.
+    pub fn skipped_region(&self) -> Option<db::View> {
+        self.skip
+    }
+    pub fn previous(&self) -> Option<Token> {
+        self.previous
     }
     pub fn peek(&mut self) -> Option<Token> {
         if self.next.is_none() {
-            self.next = lex(&mut self.chars, next_token);
+            self.next = self.lex();
         }
         self.next
     }
@@ -180,7 +207,7 @@ impl<'a> Lexer<'a> {
     }
 }
 
-pub fn escape(str: &str) -> Cow<str> {
+pub fn escape(str: &str) -> Cow<'_, str> {
     if !str.contains('\\') {
         return Cow::Borrowed(str);
     }
@@ -192,9 +219,119 @@ pub fn escape(str: &str) -> Cow<str> {
     Cow::Owned(string)
 }
 
+/// Characters that must be escaped in unquoted words.
+const UNQUOTED_SPECIAL: &str = " \t'\"\\$`*?[]#&;|<>(){}!";
+
+/// Remove quoting from a word, which may be partially typed. Returns the quote that is open at the
+/// end of the word, if any, along with the literal text. Words containing expansions or globs are
+/// rejected.
+pub fn unquote(word: &str) -> Option<(Option<char>, String)> {
+    let mut literal = String::new();
+    let mut quote = None;
+    let mut chars = word.chars();
+    while let Some(char) = chars.next() {
+        match (quote, char) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), char) => literal.push(char),
+            (_, '$' | '`') => return None,
+            (Some(_), '"') => quote = None,
+            (Some(_), '\\') => match chars.next()? {
+                char @ ('$' | '`' | '"' | '\\') => literal.push(char),
+                char => literal.extend(['\\', char]),
+            },
+            (None, '\\') => literal.push(chars.next()?),
+            (None, '\'' | '"') => quote = Some(char),
+            (None, '*' | '?' | '[') => return None,
+            (_, char) => literal.push(char),
+        }
+    }
+    Some((quote, literal))
+}
+
+/// Whether `word` contains any of the characters in `special` outside of quotes and escapes.
+/// Quotes nested in command substitutions are not tracked, so the result may be a false positive.
+pub fn contains_unquoted(word: &str, special: &str) -> bool {
+    let mut quote = None;
+    let mut chars = word.chars();
+    while let Some(char) = chars.next() {
+        match (quote, char) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => _ = chars.next(),
+            (None, '\'' | '"') => quote = Some(char),
+            (None, char) if special.contains(char) => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Quote `literal` so that it can be inserted after an opening `quote`, or unquoted.
+pub fn quote(literal: &str, quote: Option<char>) -> String {
+    let special = match quote {
+        Some('\'') => return literal.replace('\'', "'\\''"),
+        Some(_) => "$`\"\\",
+        None => UNQUOTED_SPECIAL,
+    };
+    let mut string = String::with_capacity(literal.len());
+    for char in literal.chars() {
+        if special.contains(char) {
+            string.push('\\');
+        }
+        string.push(char);
+    }
+    string
+}
+
+/// Decode the escape sequences in the body of a `$'...'` string. Sequences that can not be
+/// represented portably, like `\c` and `\u`, are rejected.
+pub fn ansi_c_unescape(body: &str) -> Option<String> {
+    let mut literal = String::with_capacity(body.len());
+    let mut chars = body.chars().peekable();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            literal.push(char);
+            continue;
+        }
+        let escape = chars.next()?;
+        let (radix, width, mut value) = match escape {
+            'x' => (16, 2, 0),
+            '0'..='7' => (8, 2, escape.to_digit(8)?),
+            char => {
+                literal.push(match char {
+                    'a' => '\x07',
+                    'b' => '\x08',
+                    'e' | 'E' => '\x1b',
+                    'f' => '\x0c',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'v' => '\x0b',
+                    '\\' | '\'' | '"' | '?' => char,
+                    _ => return None,
+                });
+                continue;
+            }
+        };
+        let mut digits = 0;
+        while digits < width {
+            let Some(digit) = chars.peek().and_then(|char| char.to_digit(radix)) else { break };
+            value = value * radix + digit;
+            digits += 1;
+            chars.next();
+        }
+        if escape == 'x' && digits == 0 {
+            return None;
+        }
+        literal.push(char::from_u32(value).filter(|&char| char != '\0' && char.is_ascii())?);
+    }
+    Some(literal)
+}
+
 pub fn is_name(str: &str) -> bool {
     let mut chars = str.chars();
-    chars.next().is_some_and(char::is_alphabetic) && chars.all(|c| c.is_alphanumeric() || c == '_')
+    let is_start = |char: char| char.is_alphabetic() || char == '_';
+    chars.next().is_some_and(is_start) && chars.all(|c| c.is_alphanumeric() || c == '_')
 }
 
 impl TokenKind {
@@ -291,8 +428,33 @@ mod tests {
         assert!(super::is_name("hello"));
         assert!(super::is_name("hello_world"));
         assert!(super::is_name("helloWorld10"));
+        assert!(super::is_name("_hello"));
         assert!(!super::is_name("10helloWorld10"));
         assert!(!super::is_name("hello-world"));
         assert!(!super::is_name(""));
     }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(super::unquote("'a b'/c"), Some((None, String::from("a b/c"))));
+        assert_eq!(super::unquote("\"a\\\"b"), Some((Some('"'), String::from("a\"b"))));
+        assert_eq!(super::unquote("a\\ b"), Some((None, String::from("a b"))));
+        assert_eq!(super::unquote("$HOME/a"), None);
+        assert_eq!(super::unquote("*.sh"), None);
+        assert!(super::contains_unquoted("a*", "*"));
+        assert!(super::contains_unquoted("\"$a\"$b", "$"));
+        assert!(!super::contains_unquoted("'*'\\*\"$a\"", "*$"));
+        assert_eq!(super::quote("a b", None), "a\\ b");
+        assert_eq!(super::quote("a b'c", Some('\'')), "a b'\\''c");
+        assert_eq!(super::quote("a \"$", Some('"')), "a \\\"\\$");
+    }
+
+    #[test]
+    fn ansi_c_unescape() {
+        assert_eq!(super::ansi_c_unescape("a\\tb\\n"), Some(String::from("a\tb\n")));
+        assert_eq!(super::ansi_c_unescape("\\x41\\101\\'"), Some(String::from("AA'")));
+        assert_eq!(super::ansi_c_unescape("\\u00e9"), None);
+        assert_eq!(super::ansi_c_unescape("\\x"), None);
+        assert_eq!(super::ansi_c_unescape("a\\"), None);
+    }
 }
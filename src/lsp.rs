@@ -59,10 +59,17 @@ pub struct Diagnostic {
     pub source: &'static str,
     pub message: String,
     pub code: i32,
+    #[serde(rename = "codeDescription", skip_serializing_if = "Option::is_none")]
+    pub code_description: Option<CodeDescription>,
     #[serde(rename = "relatedInformation", skip_serializing_if = "Vec::is_empty")]
     pub related: Vec<DiagnosticRelated>,
 }
 
+#[derive(Serialize)]
+pub struct CodeDescription {
+    pub href: String,
+}
+
 #[derive(Deserialize)]
 pub struct DocumentIdentifier {
     pub uri: DocumentURI,
@@ -90,10 +97,80 @@ pub struct ContentChange {
     pub text: String,
 }
 
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CompletionItemClientCapabilities {
+    pub snippet_support: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CompletionClientCapabilities {
+    pub completion_item: CompletionItemClientCapabilities,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct TextDocumentClientCapabilities {
+    pub diagnostic: Option<serde::de::IgnoredAny>,
+    pub completion: CompletionClientCapabilities,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DiagnosticWorkspaceClientCapabilities {
+    pub refresh_support: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceClientCapabilities {
+    pub diagnostics: DiagnosticWorkspaceClientCapabilities,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ClientCapabilities {
+    #[serde(rename = "textDocument")]
+    pub text_document: TextDocumentClientCapabilities,
+    pub workspace: WorkspaceClientCapabilities,
+}
+
+#[derive(Deserialize)]
+pub struct WorkspaceFolder {
+    pub uri: String,
+}
+
 #[derive(Deserialize)]
 pub struct InitializeParams {
     #[serde(rename = "initializationOptions")]
     pub settings: Option<config::Settings>,
+    #[serde(default)]
+    pub capabilities: ClientCapabilities,
+    #[serde(rename = "rootUri")]
+    pub root_uri: Option<String>,
+    #[serde(rename = "workspaceFolders")]
+    pub workspace_folders: Option<Vec<WorkspaceFolder>>,
+}
+
+#[derive(Deserialize)]
+pub struct DocumentDiagnosticParams {
+    #[serde(rename = "textDocument")]
+    pub document: DocumentIdentifier,
+    #[serde(rename = "previousResultId")]
+    pub previous_result_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PreviousResultId {
+    pub uri: DocumentURI,
+    pub value: String,
+}
+
+#[derive(Deserialize)]
+pub struct WorkspaceDiagnosticParams {
+    #[serde(rename = "previousResultIds")]
+    pub previous_result_ids: Vec<PreviousResultId>,
 }
 
 #[derive(Deserialize)]
@@ -138,6 +215,26 @@ pub struct RenameParams {
     pub new_name: String,
 }
 
+#[derive(Deserialize)]
+pub struct ExecuteCommandParams {
+    pub command: String,
+    #[serde(default)]
+    pub arguments: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+pub struct FileRename {
+    #[serde(rename = "oldUri")]
+    pub old_uri: DocumentURI,
+    #[serde(rename = "newUri")]
+    pub new_uri: DocumentURI,
+}
+
+#[derive(Deserialize)]
+pub struct RenameFilesParams {
+    pub files: Vec<FileRename>,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub enum MarkupKind {
@@ -163,9 +260,18 @@ pub enum CompletionItemKind {
     Text = 1,
     Function = 3,
     Variable = 6,
+    Value = 12,
+    Keyword = 14,
     Snippet = 15,
     File = 17,
-    Directory = 18,
+    Directory = 19,
+    Operator = 24,
+}
+
+#[derive(Clone, Copy)]
+pub enum InsertTextFormat {
+    PlainText = 1,
+    Snippet = 2,
 }
 
 #[derive(Serialize)]
@@ -174,10 +280,33 @@ pub struct CompletionItem {
     pub kind: CompletionItemKind,
     #[serde(rename = "textEdit")]
     pub edit: TextEdit,
+    #[serde(rename = "insertTextFormat", skip_serializing_if = "Option::is_none")]
+    pub insert_text_format: Option<InsertTextFormat>,
+    #[serde(rename = "sortText", skip_serializing_if = "Option::is_none")]
+    pub sort_text: Option<String>,
+    #[serde(rename = "filterText", skip_serializing_if = "Option::is_none")]
+    pub filter_text: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub detail: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub documentation: Option<MarkupContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<CompletionData>,
+}
+
+#[derive(Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionList {
+    pub is_incomplete: bool,
+    pub items: Vec<CompletionItem>,
+}
+
+/// Identifies the symbol a completion item refers to, for `completionItem/resolve`.
+#[derive(Serialize, Deserialize)]
+pub struct CompletionData {
+    pub uri: DocumentURI,
+    pub symbol: u32,
+    pub name: String,
 }
 
 #[derive(Clone, Copy)]
@@ -227,24 +356,44 @@ pub struct DidChangeConfigurationParams {
     pub settings: SettingsContainer,
 }
 
-#[derive(Clone, Copy)]
+/// The order must match `SemanticTokenKind`.
+pub const SEMANTIC_TOKEN_KINDS: &[&str] =
+    &["keyword", "parameter", "string", "function", "variable", "comment", "number", "operator"];
+
+/// The order must match `SemanticTokenModifier`.
+pub const SEMANTIC_TOKEN_MODIFIERS: &[&str] =
+    &["documentation", "declaration", "readonly", "defaultLibrary", "modification"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum SemanticTokenKind {
     Keyword = 0,
     Parameter = 1,
     String = 2,
+    Function = 3,
+    Variable = 4,
+    Comment = 5,
+    Number = 6,
+    Operator = 7,
 }
 
 #[derive(Clone, Copy)]
 pub enum SemanticTokenModifier {
-    None = 0,
-    Documentation = 1,
+    Documentation = 1 << 0,
+    Declaration = 1 << 1,
+    Readonly = 1 << 2,
+    DefaultLibrary = 1 << 3,
+    Modification = 1 << 4,
 }
 
+/// A set of `SemanticTokenModifier` flags.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct SemanticTokenModifiers(pub u32);
+
 pub struct SemanticToken {
     pub position: Position,
     pub width: u32,
     pub kind: SemanticTokenKind,
-    pub modifier: SemanticTokenModifier,
+    pub modifiers: SemanticTokenModifiers,
 }
 
 #[derive(Default)]
@@ -252,6 +401,22 @@ pub struct SemanticTokensData {
     pub data: Vec<SemanticToken>,
 }
 
+#[derive(Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensEdit {
+    pub start: u32,
+    pub delete_count: u32,
+    pub data: Vec<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct SemanticTokensDeltaParams {
+    #[serde(rename = "textDocument")]
+    pub document: DocumentIdentifier,
+    #[serde(rename = "previousResultId")]
+    pub previous_result_id: String,
+}
+
 impl Position {
     pub fn advance(&mut self, char: char) {
         if char == '\n' {
@@ -283,6 +448,27 @@ impl Range {
     }
 }
 
+impl SemanticTokenModifiers {
+    pub const NONE: Self = Self(0);
+    pub fn with(self, modifier: SemanticTokenModifier) -> Self {
+        Self(self.0 | modifier as u32)
+    }
+}
+
+impl From<SemanticTokenModifier> for SemanticTokenModifiers {
+    fn from(modifier: SemanticTokenModifier) -> Self {
+        Self::NONE.with(modifier)
+    }
+}
+
+impl SemanticToken {
+    /// Make a token that covers `range`, which must not span multiple lines.
+    pub fn new(range: Range, kind: SemanticTokenKind, modifiers: SemanticTokenModifiers) -> Self {
+        let width = range.end.character - range.start.character;
+        Self { position: range.start, width, kind, modifiers }
+    }
+}
+
 impl Diagnostic {
     pub fn new(range: Range, severity: Severity, message: impl Into<String>) -> Self {
         Self {
@@ -291,6 +477,7 @@ impl Diagnostic {
             source: "shell-language-server",
             message: message.into(),
             code: 0, // todo
+            code_description: None,
             related: Vec::new(),
         }
     }
@@ -320,6 +507,13 @@ impl Location {
     }
 }
 
+impl DocumentURI {
+    /// Parse a URI with the file scheme.
+    pub fn parse(uri: &str) -> Option<Self> {
+        uri.strip_prefix("file://").map(|path| Self { path: PathBuf::from(path) })
+    }
+}
+
 impl Display for DocumentURI {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "file://{}", self.path.display())
@@ -334,8 +528,7 @@ impl serde::de::Visitor<'_> for DocumentURIVisitor {
         formatter.write_str("a URI with file scheme")
     }
     fn visit_str<E: serde::de::Error>(self, str: &str) -> Result<DocumentURI, E> {
-        let uri = |path| DocumentURI { path: PathBuf::from(path) };
-        str.strip_prefix("file://").map(uri).ok_or_else(|| E::custom("bad URI scheme"))
+        DocumentURI::parse(str).ok_or_else(|| E::custom("bad URI scheme"))
     }
 }
 
@@ -351,24 +544,46 @@ impl Serialize for DocumentURI {
     }
 }
 
-impl Serialize for SemanticTokensData {
-    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
-        use serde::ser::SerializeSeq;
-        let mut seq = s.serialize_seq(Some(self.data.len() * 5))?;
-        let mut prev = Position::default();
-        for &SemanticToken { position, width, kind, modifier } in &self.data {
-            if position.line != prev.line {
-                prev.character = 0;
-            }
-            let mut elem = |elem: u32| seq.serialize_element(&elem);
-            elem(position.line - prev.line)?;
-            elem(position.character - prev.character)?;
-            elem(width)?;
-            elem(kind as u32)?;
-            elem(modifier as u32)?;
-            prev = position;
+impl SemanticTokensData {
+    /// Get the tokens that start within `range`.
+    pub fn range(&self, range: Range) -> &[SemanticToken] {
+        let start = self.data.partition_point(|token| token.position < range.start);
+        let end = self.data.partition_point(|token| token.position < range.end);
+        &self.data[start..end.max(start)]
+    }
+}
+
+/// Encode semantic tokens in the relative integer format used by the protocol.
+pub fn encode_semantic_tokens(tokens: &[SemanticToken]) -> Vec<u32> {
+    let mut data = Vec::with_capacity(tokens.len() * 5);
+    let mut prev = Position::default();
+    for &SemanticToken { position, width, kind, modifiers } in tokens {
+        if position.line != prev.line {
+            prev.character = 0;
         }
-        seq.end()
+        data.extend([
+            position.line - prev.line,
+            position.character - prev.character,
+            width,
+            kind as u32,
+            modifiers.0,
+        ]);
+        prev = position;
+    }
+    data
+}
+
+/// Describe the difference between `old` and `new` as a single edit.
+pub fn semantic_tokens_edit(old: &[u32], new: &[u32]) -> SemanticTokensEdit {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = (old[prefix..].iter().rev())
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    SemanticTokensEdit {
+        start: prefix as u32,
+        delete_count: (old.len() - prefix - suffix) as u32,
+        data: new[prefix..new.len() - suffix].to_vec(),
     }
 }
 
@@ -384,5 +599,24 @@ macro_rules! serialize_as_i32 {
 
 serialize_as_i32!(Severity);
 serialize_as_i32!(CompletionItemKind);
+serialize_as_i32!(InsertTextFormat);
 serialize_as_i32!(ReferenceKind);
 serialize_as_i32!(SymbolKind);
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn semantic_tokens_edit() {
+        let edit = |start, delete_count, data: &[u32]| super::SemanticTokensEdit {
+            start,
+            delete_count,
+            data: data.to_vec(),
+        };
+        assert_eq!(super::semantic_tokens_edit(&[1, 2, 3], &[1, 2, 3]), edit(3, 0, &[]));
+        assert_eq!(super::semantic_tokens_edit(&[1, 2, 3], &[1, 4, 3]), edit(1, 1, &[4]));
+        assert_eq!(super::semantic_tokens_edit(&[1, 2, 3], &[1, 3]), edit(1, 1, &[]));
+        assert_eq!(super::semantic_tokens_edit(&[1, 3], &[1, 2, 2, 3]), edit(1, 0, &[2, 2]));
+        assert_eq!(super::semantic_tokens_edit(&[], &[1, 2]), edit(0, 0, &[1, 2]));
+        assert_eq!(super::semantic_tokens_edit(&[1, 1], &[1]), edit(1, 1, &[]));
+    }
+}
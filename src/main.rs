@@ -2,6 +2,8 @@
 
 use std::process::ExitCode;
 
+mod actions;
+mod complete;
 mod config;
 mod db;
 mod env;
@@ -10,10 +12,12 @@ mod indexvec;
 mod lex;
 mod lsp;
 mod parse;
+mod paths;
 mod poschars;
 mod rpc;
 mod server;
 mod shell;
+mod syntax;
 
 const HELP: &str = r"Options:
   --help, -h           Display help information.
@@ -1,5 +1,7 @@
 use crate::config::Settings;
+use crate::external::shellcheck;
 use crate::lex::{self, Lexer, Token, TokenKind};
+use crate::poschars::PosChars;
 use crate::shell::{self, Shell};
 use crate::{db, env, lsp};
 use std::borrow::Cow;
@@ -26,6 +28,37 @@ struct FunctionState {
     shift: usize,
 }
 
+/// The syntactic position of a word that is being completed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CompletionPosition {
+    Command,
+    /// An argument to `command`, or to a compound command such as `for` if `command` is `None`.
+    Argument { command: Option<String> },
+    Redirect,
+    HeredocDelimiter,
+    CasePattern,
+    /// The name of a variable given to a builtin such as `export`, or declared by `for`.
+    VariableName,
+    /// The name of a function given to `unset -f`.
+    FunctionName,
+}
+
+/// What may appear at the end of a partial document: a word in some position, reserved words,
+/// or both.
+#[derive(Default, PartialEq, Eq, Debug)]
+pub struct CompletionContext {
+    pub position: Option<CompletionPosition>,
+    pub keywords: Vec<&'static str>,
+}
+
+/// The completion context is closed once something other than the recorded alternatives is
+/// required at the end of the input, so that enclosing constructs can not add to it.
+#[derive(Default)]
+struct CompletionState {
+    context: CompletionContext,
+    closed: bool,
+}
+
 struct Context<'a> {
     info: db::DocumentInfo,
     lexer: Lexer<'a>,
@@ -35,6 +68,21 @@ struct Context<'a> {
     variables: HashMap<String, db::SymbolId>,
     annotations: Annotations,
     script_params: Parameters,
+    completion: CompletionState,
+    /// The quoting of the expansion being parsed.
+    quoting: db::Quoting,
+    /// The range of the interpreter name in the shebang.
+    interpreter: Option<lsp::Range>,
+    /// Unquoted expansions in the command argument being parsed.
+    argument_expansions: Option<Vec<db::Location>>,
+    /// Shellcheck directives that apply to the next statement.
+    directives: Vec<Directive>,
+}
+
+/// A `# shellcheck` directive comment, along with the codes it disables.
+struct Directive {
+    range: lsp::Range,
+    codes: Vec<(std::ops::RangeInclusive<i32>, lsp::Range)>,
 }
 
 impl<'a> Context<'a> {
@@ -48,6 +96,11 @@ impl<'a> Context<'a> {
             variables: HashMap::new(),
             annotations: Annotations::default(),
             script_params: Parameters::default(),
+            completion: CompletionState::default(),
+            quoting: db::Quoting::Unquoted,
+            interpreter: None,
+            argument_expansions: None,
+            directives: Vec::new(),
         }
     }
     fn error(&mut self, message: impl Into<String>) -> lsp::Diagnostic {
@@ -58,10 +111,43 @@ impl<'a> Context<'a> {
         self.error(format!("Expected {}, but found {}", description, found))
     }
     fn expect(&mut self, kind: TokenKind) -> ParseResult<Token> {
-        self.lexer.next_if_kind(kind).ok_or_else(|| self.expected(kind.show()))
+        let token = self.lexer.next_if_kind(kind);
+        token.ok_or_else(|| {
+            self.complete_nothing_else();
+            self.expected(kind.show())
+        })
     }
-    fn expect_word(&mut self, keyword: &str) -> ParseResult<()> {
-        if parse_keyword(self, keyword) { Ok(()) } else { Err(self.expected(keyword)) }
+    fn expect_word(&mut self, keyword: &'static str) -> ParseResult<()> {
+        if parse_reserved_word(self, keyword) {
+            Ok(())
+        }
+        else {
+            self.complete_nothing_else();
+            Err(self.expected(keyword))
+        }
+    }
+    fn at_end(&mut self) -> bool {
+        self.lexer.peek().is_none()
+    }
+    /// If the input ends here, note that it could continue with a word in `position`.
+    /// Unless `more` is set, nothing else could follow.
+    fn complete(&mut self, position: CompletionPosition, more: bool) {
+        if self.at_end() && !self.completion.closed {
+            self.completion.context.position.get_or_insert(position);
+            self.completion.closed = !more;
+        }
+    }
+    /// If the input ends here, note that it could continue with `keyword`.
+    fn complete_keyword(&mut self, keyword: &'static str) {
+        if self.at_end() && !self.completion.closed {
+            self.completion.context.keywords.push(keyword);
+        }
+    }
+    /// If the input ends here, note that it could not continue with anything not yet recorded.
+    fn complete_nothing_else(&mut self) {
+        if self.at_end() {
+            self.completion.closed = true;
+        }
     }
     fn consume(&mut self, kind: TokenKind) -> bool {
         self.lexer.next_if_kind(kind).is_some()
@@ -75,6 +161,10 @@ impl<'a> Context<'a> {
     fn inform(&mut self, range: lsp::Range, message: impl Into<String>) {
         self.emit(lsp::Diagnostic::info(range, message))
     }
+    fn token(&mut self, range: lsp::Range, kind: lsp::SemanticTokenKind) {
+        let modifiers = lsp::SemanticTokenModifiers::NONE;
+        self.info.tokens.data.push(lsp::SemanticToken::new(range, kind, modifiers));
+    }
 }
 
 fn location(first: Token, last: Token) -> db::Location {
@@ -110,13 +200,6 @@ fn document_scope_special(ctx: &mut Context, name: &str, special: db::Special) -
 }
 
 fn parameter_symbol(ctx: &mut Context, range: lsp::Range, index: usize) -> db::SymbolId {
-    ctx.info.tokens.data.push(lsp::SemanticToken {
-        position: range.start,
-        width: range.end.character - range.start.character,
-        kind: lsp::SemanticTokenKind::Parameter,
-        modifier: lsp::SemanticTokenModifier::None,
-    });
-
     if index == 0 {
         return document_scope_special(ctx, "0", db::Special::Zero);
     }
@@ -163,14 +246,16 @@ fn variable_symbol(ctx: &mut Context, word: Token) -> db::SymbolId {
         .unwrap_or_else(|| new_variable(ctx, name.into_owned()))
 }
 
-fn add_cmd_ref(ctx: &mut Context, word: Token) {
+fn add_cmd_ref(ctx: &mut Context, word: Token) -> db::SymbolId {
     let id = command_symbol(ctx, word);
     ctx.info.references.push(db::SymbolReference::read(word.range, id));
+    id
 }
 
 fn add_var_read(ctx: &mut Context, word: Token) -> db::SymbolId {
     let id = variable_symbol(ctx, word);
-    ctx.info.references.push(db::SymbolReference::read(word.range, id));
+    let reference = db::SymbolReference::read(word.range, id);
+    ctx.info.references.push(db::SymbolReference { quoting: ctx.quoting, ..reference });
     id
 }
 
@@ -204,6 +289,58 @@ fn unset_function(ctx: &mut Context, word: Token) {
     ctx.warn(word.range, format!("'{name}' is not a function"));
 }
 
+/// An edit that makes the document a Bash script, if its shebang can be changed.
+fn bash_shebang(ctx: &Context) -> Option<(&'static str, lsp::TextEdit)> {
+    match ctx.interpreter {
+        Some(range) => Some(("Change the shebang to bash", lsp::TextEdit {
+            range,
+            new_text: String::from("bash"),
+        })),
+        None if ctx.document.starts_with("#!") => None,
+        None => Some(("Add a bash shebang", lsp::TextEdit {
+            range: lsp::Range::default(),
+            new_text: String::from("#!/bin/bash\n"),
+        })),
+    }
+}
+
+/// Record the use of a Bash extension. In POSIX shell scripts, it is reported along with actions
+/// that rewrite it portably, if possible, or change the shebang.
+fn add_bashism(
+    ctx: &mut Context,
+    kind: db::BashismKind,
+    range: lsp::Range,
+    rewrite: Option<(&str, Vec<lsp::TextEdit>)>,
+) {
+    ctx.info.bashisms.push(db::Bashism { kind, range });
+    if ctx.info.shell != Shell::Posix {
+        return;
+    }
+    ctx.warn(range, kind.message());
+    let shebang = bash_shebang(ctx).map(|(title, edit)| (title, vec![edit]));
+    for (title, edits) in rewrite.into_iter().chain(shebang) {
+        let kind = db::ActionKind::Edit { title: String::from(title), edits };
+        ctx.info.actions.push(db::Action { kind, range });
+    }
+}
+
+/// Report a problem that Shellcheck would report with `code`, along with an action that fixes it,
+/// if possible. Shellcheck diagnostics replace these when they are available.
+fn add_fix(
+    ctx: &mut Context,
+    code: i32,
+    diagnostic: lsp::Diagnostic,
+    fix: Option<(&str, Vec<lsp::TextEdit>)>,
+) {
+    let range = diagnostic.range;
+    let code_description = Some(shellcheck::code_description(code));
+    ctx.emit(lsp::Diagnostic { code, code_description, ..diagnostic });
+    if let Some((title, edits)) = fix {
+        let kind = db::ActionKind::Fix { code, title: String::from(title), edits };
+        ctx.info.actions.push(db::Action { kind, range });
+    }
+}
+
 fn protected(ctx: &mut Context, callback: impl FnOnce(&mut Context) -> ParseResult<bool>) -> bool {
     match callback(ctx) {
         Ok(result) => result,
@@ -214,8 +351,20 @@ fn protected(ctx: &mut Context, callback: impl FnOnce(&mut Context) -> ParseResu
     }
 }
 
-fn is_identifier(str: &str, shell: Shell) -> bool {
-    if shell == Shell::Bash { str.chars().all(|char| char != '$') } else { lex::is_name(str) }
+/// Characters that can not appear in Bash function names: whitespace, metacharacters, quotes,
+/// expansions, globs, and the equals sign of assignments.
+const NON_IDENTIFIER_CHARS: &str = " \t\n;&|<>()'\"\\`$*?[=";
+
+/// Whether `str` is a valid function name in `shell`.
+pub fn is_identifier(str: &str, shell: Shell) -> bool {
+    if shell == Shell::Bash {
+        !str.is_empty()
+            && !str.starts_with('-')
+            && !str.contains(|char| NON_IDENTIFIER_CHARS.contains(char))
+    }
+    else {
+        lex::is_name(str)
+    }
 }
 
 const END_KINDS: &[TokenKind] = {
@@ -225,7 +374,7 @@ const END_KINDS: &[TokenKind] = {
 
 const REDIRECT_KINDS: &[TokenKind] = {
     use TokenKind::*;
-    &[Great, GreatGreat, Less, LessLess, GreatPipe, GreatAnd, LessAnd]
+    &[Great, GreatGreat, Less, LessLess, LessLessDash, LessGreat, GreatPipe, GreatAnd, LessAnd]
 };
 
 const CONTINUATION_KINDS: &[TokenKind] = {
@@ -248,6 +397,17 @@ fn add_description(ctx: &mut Context, annotation: db::View) {
     }
 }
 
+/// The directives recognized by `parse_comment`, along with their descriptions.
+pub const ANNOTATION_DIRECTIVES: &[(&str, &str)] = &[
+    ("desc", "Provide a general description of the next function or variable."),
+    (
+        "param",
+        "Provide a description of the current function parameter or script parameter. \
+         The first annotation applies to `$1`, the second one to `$2`, and so on.",
+    ),
+    ("script", "Apply previous `param` annotations to the script instead of the next function."),
+];
+
 fn parse_comment(ctx: &mut Context, comment: Token) {
     if comment.kind != TokenKind::Comment {
         return;
@@ -256,12 +416,13 @@ fn parse_comment(ctx: &mut Context, comment: Token) {
         let offset = line.find(char::is_whitespace).unwrap_or(line.len());
         let arg_width = line[offset..].trim_start().len() as u32;
         let annotation = db::View { start: comment.view.end - arg_width, end: comment.view.end };
+        let documentation = lsp::SemanticTokenModifier::Documentation.into();
 
         ctx.info.tokens.data.push(lsp::SemanticToken {
             position: comment.range.start,
             width: comment.range.end.character - comment.range.start.character - arg_width,
             kind: lsp::SemanticTokenKind::Keyword,
-            modifier: lsp::SemanticTokenModifier::Documentation,
+            modifiers: documentation,
         });
 
         let arg = lsp::SemanticToken {
@@ -271,7 +432,7 @@ fn parse_comment(ctx: &mut Context, comment: Token) {
             },
             width: arg_width,
             kind: lsp::SemanticTokenKind::Keyword, // placeholder
-            modifier: lsp::SemanticTokenModifier::Documentation,
+            modifiers: documentation,
         };
 
         let arg_range =
@@ -311,6 +472,171 @@ fn parse_comment(ctx: &mut Context, comment: Token) {
             directive => ctx.warn(comment.range, format!("Unrecognized directive: '{directive}'")),
         }
     }
+    else if let Some(directives) = shellcheck_directives(comment.view.string(ctx.document)) {
+        parse_shellcheck_directive(ctx, comment, directives);
+    }
+    else {
+        ctx.token(comment.range, lsp::SemanticTokenKind::Comment);
+    }
+}
+
+/// Shellcheck codes that are also checked natively.
+pub const NATIVE_CODES: &[i32] = &[2006, 2007, 2046, 2048, 2068, 2086, 3014, 3037];
+
+/// Get the key-value pairs of a `# shellcheck` directive comment.
+fn shellcheck_directives(comment: &str) -> Option<&str> {
+    let rest = comment.strip_prefix('#')?.trim_start().strip_prefix("shellcheck")?;
+    rest.starts_with(char::is_whitespace).then_some(rest)
+}
+
+/// Parse a Shellcheck code such as `SC2086` or `2086`.
+fn parse_shellcheck_code(code: &str) -> Option<i32> {
+    let digits = code.strip_prefix("SC").unwrap_or(code);
+    (digits.len() == 4 && digits.bytes().all(|byte| byte.is_ascii_digit()))
+        .then(|| digits.parse().ok())?
+}
+
+/// Split a comma-separated directive value into items along with their byte offsets.
+fn directive_items(value: &str) -> impl Iterator<Item = (usize, &str)> {
+    value.split(',').scan(0, |offset, item| {
+        let start = *offset;
+        *offset += item.len() + 1;
+        Some((start, item))
+    })
+}
+
+/// Parse the comma-separated codes of a `disable=` directive.
+fn parse_disabled_codes(
+    ctx: &mut Context,
+    value: &str,
+    range: impl Fn(usize, usize) -> lsp::Range,
+) -> Vec<(std::ops::RangeInclusive<i32>, lsp::Range)> {
+    let mut codes = Vec::new();
+    for (start, item) in directive_items(value) {
+        let item_range = range(start, start + item.len());
+        let codes_range = match item.split_once('-') {
+            _ if item == "all" => Some(0..=i32::MAX),
+            Some((first, last)) => parse_shellcheck_code(first)
+                .zip(parse_shellcheck_code(last))
+                .map(|(first, last)| first..=last),
+            None => parse_shellcheck_code(item).map(|code| code..=code),
+        };
+        match codes_range {
+            Some(codes_range) => {
+                let kind = if item == "all" {
+                    lsp::SemanticTokenKind::Keyword
+                }
+                else {
+                    lsp::SemanticTokenKind::Number
+                };
+                ctx.token(item_range, kind);
+                codes.push((codes_range, item_range));
+            }
+            None => ctx.warn(item_range, format!("Invalid Shellcheck code: '{item}'")),
+        }
+    }
+    codes
+}
+
+/// Check that `value` is one of `allowed`.
+fn check_directive_value(
+    ctx: &mut Context,
+    value: &str,
+    range: lsp::Range,
+    mut allowed: impl Iterator<Item = &'static str>,
+    description: &str,
+) {
+    if allowed.any(|allowed| allowed == value) {
+        ctx.token(range, lsp::SemanticTokenKind::String);
+    }
+    else {
+        ctx.warn(range, format!("Unknown {description}: '{value}'"));
+    }
+}
+
+/// Highlight and validate the key-value pairs of a `# shellcheck` directive comment.
+fn parse_shellcheck_directive(ctx: &mut Context, comment: Token, directives: &str) {
+    let text = comment.view.string(ctx.document);
+    let position = |offset: usize| {
+        comment.range.start.horizontal_offset(text[..offset].chars().count() as u32)
+    };
+    let range = |start, end| lsp::Range { start: position(start), end: position(end) };
+
+    let keyword_end = text.len() - directives.len();
+    ctx.token(range(0, keyword_end), lsp::SemanticTokenKind::Comment);
+
+    let mut codes = Vec::new();
+    let mut offset = keyword_end;
+    for word in directives.split(char::is_whitespace) {
+        let start = offset;
+        offset += word.len() + 1;
+        if word.is_empty() {
+            continue;
+        }
+        let Some((key, value)) = word.split_once('=')
+        else {
+            ctx.warn(range(start, start + word.len()), "Expected a `key=value` pair");
+            continue;
+        };
+        let key_range = range(start, start + key.len());
+        if !shellcheck::DIRECTIVE_KEYS.iter().any(|&(known, _)| known == key) {
+            ctx.warn(key_range, format!("Unknown Shellcheck directive: '{key}'"));
+            continue;
+        }
+        ctx.token(key_range, lsp::SemanticTokenKind::Keyword);
+        let value_start = start + key.len() + 1;
+        let value_range = range(value_start, value_start + value.len());
+        if value.is_empty() {
+            ctx.warn(key_range, format!("Expected a value for '{key}'"));
+            continue;
+        }
+        let item_range = |start: usize, end: usize| range(value_start + start, value_start + end);
+        match key {
+            "disable" => codes.extend(parse_disabled_codes(ctx, value, item_range)),
+            "enable" => {
+                for (start, item) in directive_items(value) {
+                    let checks = shellcheck::OPTIONAL_CHECKS.iter().map(|&(check, _)| check);
+                    let checks = checks.chain(std::iter::once("all"));
+                    let range = item_range(start, start + item.len());
+                    check_directive_value(ctx, item, range, checks, "optional check");
+                }
+            }
+            "shell" => {
+                let shells = shellcheck::SHELLS.iter().copied();
+                check_directive_value(ctx, value, value_range, shells, "shell");
+            }
+            "external-sources" => {
+                let values = ["true", "false"].into_iter();
+                check_directive_value(ctx, value, value_range, values, "value");
+            }
+            _ => ctx.token(value_range, lsp::SemanticTokenKind::String),
+        }
+    }
+
+    let line_start = ctx.document[..comment.view.start as usize].rfind('\n').map_or(0, |i| i + 1);
+    if !ctx.document[line_start..comment.view.start as usize].trim().is_empty() {
+        let message = "Shellcheck directives have no effect after a command on the same line";
+        ctx.warn(comment.range, message);
+    }
+    else if ctx.info.statements.is_empty() && ctx.function.is_none() {
+        // Directives before the first statement apply to the whole script.
+        if !codes.is_empty() {
+            let scope = lsp::Range::MAX;
+            let directive = comment.range;
+            ctx.info.suppressions.push(db::Suppression { directive, codes, scope });
+        }
+    }
+    else {
+        ctx.directives.push(Directive { range: comment.range, codes });
+    }
+}
+
+/// Warn about directives that are not followed by a statement.
+fn discard_directives(ctx: &mut Context) {
+    for directive in std::mem::take(&mut ctx.directives) {
+        let message = "This Shellcheck directive has no effect, because no command follows it";
+        ctx.warn(directive.range, message);
+    }
 }
 
 fn skip_whitespace(ctx: &mut Context) {
@@ -329,27 +655,36 @@ fn skip_empty_lines(ctx: &mut Context) {
 
 fn expect_statement_end(ctx: &mut Context) -> ParseResult<()> {
     skip_whitespace(ctx);
-    if ctx.lexer.next_if(kind_matches(END_KINDS)).is_some() {
+    if let Some(token) = ctx.lexer.next_if(kind_matches(END_KINDS)) {
+        if token.kind == TokenKind::Semi {
+            ctx.token(token.range, lsp::SemanticTokenKind::Operator);
+        }
         skip_whitespace(ctx);
         Ok(())
     }
     else {
+        ctx.complete_nothing_else();
         Err(ctx.expected("a new line or a semicolon"))
     }
 }
 
 fn extract_enclosed_statements(ctx: &mut Context, end: impl Copy + Fn(Token) -> bool) {
-    while !ctx.lexer.peek().is_none_or(end) {
+    let quoting = std::mem::take(&mut ctx.quoting);
+    let expansions = ctx.argument_expansions.take();
+    loop {
         skip_empty_lines(ctx);
-        if ctx.lexer.peek().is_some_and(end) {
+        ctx.complete(CompletionPosition::Command, true);
+        if ctx.lexer.peek().is_none_or(end) {
             break;
         }
         if let Err(diagnostic) = extract_statement_up_to(ctx, end) {
             ctx.emit(diagnostic);
             ctx.lexer.next();
         }
-        ctx.consume(TokenKind::Semi);
+        parse_operator(ctx, TokenKind::Semi);
     }
+    ctx.quoting = quoting;
+    ctx.argument_expansions = expansions;
 }
 
 fn is_keyword(document: &str, token: Token, keywords: &[&str]) -> bool {
@@ -362,11 +697,38 @@ fn parse_keyword(ctx: &mut Context, keyword: &str) -> bool {
     ctx.lexer.next_if(predicate).is_some()
 }
 
+fn parse_reserved_word(ctx: &mut Context, keyword: &'static str) -> bool {
+    let predicate = |token: Token| is_keyword(ctx.document, token, &[keyword]);
+    if let Some(token) = ctx.lexer.next_if(predicate) {
+        ctx.token(token.range, lsp::SemanticTokenKind::Keyword);
+        true
+    }
+    else {
+        ctx.complete_keyword(keyword);
+        false
+    }
+}
+
+fn parse_operator(ctx: &mut Context, kind: TokenKind) -> bool {
+    if let Some(token) = ctx.lexer.next_if_kind(kind) {
+        ctx.token(token.range, lsp::SemanticTokenKind::Operator);
+        true
+    }
+    else {
+        false
+    }
+}
+
 fn parse_word(ctx: &mut Context) -> ParseResult<bool> {
     if let Some(dollar) = ctx.lexer.next_if_kind(TokenKind::Dollar) {
         extract_potential_expansion(dollar, ctx)?;
     }
-    else if !ctx.consume(TokenKind::Word) {
+    else if let Some(word) = ctx.lexer.next_if_kind(TokenKind::Word) {
+        if word.view.string(ctx.document).bytes().all(|byte| byte.is_ascii_digit()) {
+            ctx.token(word.range, lsp::SemanticTokenKind::Number);
+        }
+    }
+    else {
         return Ok(false);
     }
     Ok(true)
@@ -380,9 +742,10 @@ fn parse_simple_value(ctx: &mut Context) -> ParseResult<bool> {
     if let Some(quote) = ctx.lexer.next_if_kind(TokenKind::DoubleQuote) {
         parse_string(ctx, quote);
     }
-    else if ctx.consume(TokenKind::BackQuote) {
+    else if let Some(open) = ctx.lexer.next_if_kind(TokenKind::BackQuote) {
         extract_enclosed_statements(ctx, kind_matches(&[TokenKind::BackQuote]));
-        ctx.expect(TokenKind::BackQuote)?;
+        let close = ctx.expect(TokenKind::BackQuote)?;
+        replace_backquotes(ctx, open, close);
     }
     else if ctx.lexer.next_if(kind_matches(KINDS)).is_none() {
         return parse_word(ctx);
@@ -400,47 +763,348 @@ fn parse_value(ctx: &mut Context) -> ParseResult<bool> {
     }
 }
 
+fn parse_argument(ctx: &mut Context) -> ParseResult<Option<db::Location>> {
+    let Some(first) = ctx.lexer.peek() else { return Ok(None) };
+    if !parse_value(ctx)? {
+        return Ok(None);
+    }
+    Ok(ctx.lexer.previous().map(|last| location(first, last)))
+}
+
+/// Emit string tokens for the lines of `text`, which starts at the beginning of `line`.
+/// The `holes` are sorted ranges that should not be covered by the tokens.
+fn string_tokens(ctx: &mut Context, line: u32, text: &str, holes: &[lsp::Range]) {
+    for (line, text) in (line..).zip(text.lines()) {
+        let width = text.chars().count() as u32;
+        let range = |start, end| lsp::Range {
+            start: lsp::Position { line, character: start },
+            end: lsp::Position { line, character: end },
+        };
+        let mut character = 0;
+        for hole in holes.iter().filter(|hole| hole.start.line <= line && line <= hole.end.line) {
+            let start = if hole.start.line < line { 0 } else { hole.start.character };
+            let end = if hole.end.line > line { width } else { hole.end.character };
+            if character < start {
+                ctx.token(range(character, start), lsp::SemanticTokenKind::String);
+            }
+            character = character.max(end);
+        }
+        if character < width {
+            ctx.token(range(character, width), lsp::SemanticTokenKind::String);
+        }
+    }
+}
+
+/// Parse the expansions in an unquoted here-document body, returning the ranges they cover.
+fn extract_heredoc_expansions(ctx: &mut Context, body: db::Location) -> Vec<lsp::Range> {
+    let mut expansions = Vec::new();
+    let mut chars = PosChars::resume(ctx.document, body.range.start, body.view.start);
+    while chars.offset < body.view.end {
+        let (position, offset) = (chars.position, chars.offset);
+        match chars.next() {
+            Some('\\') => _ = chars.next(),
+            Some('$') => {
+                let lexer = Lexer::resume(ctx.document, position, offset);
+                let lexer = std::mem::replace(&mut ctx.lexer, lexer);
+                let dollar = ctx.lexer.next().expect("should be a dollar sign");
+                let quoting = std::mem::replace(&mut ctx.quoting, db::Quoting::HereDocument);
+                if let Err(diagnostic) = extract_potential_expansion(dollar, ctx) {
+                    ctx.emit(diagnostic);
+                }
+                ctx.quoting = quoting;
+                let last = ctx.lexer.previous().unwrap_or(dollar);
+                ctx.lexer = lexer;
+                expansions.push(lsp::Range { start: position, end: last.range.end });
+                while chars.offset < last.view.end && chars.next().is_some() {}
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    expansions
+}
+
+fn extract_heredoc(ctx: &mut Context, delimiter: db::Location, strip_tabs: bool) {
+    let raw = delimiter.view.string(ctx.document);
+    let quoted = raw.contains(['\'', '"', '\\']);
+    let delimiter_text: String = raw.chars().filter(|char| !"'\"\\".contains(*char)).collect();
+
+    // The body begins on the next line, or after the previous here-document on the same line.
+    let pending = ctx.lexer.skipped_region();
+    let start = match pending {
+        Some(region) => region.end as usize,
+        None => match ctx.document[delimiter.view.end as usize..].find('\n') {
+            Some(index) => delimiter.view.end as usize + index + 1,
+            None => ctx.document.len(),
+        },
+    };
+
+    let mut offset = start;
+    let (body_end, region_end) = loop {
+        let rest = &ctx.document[offset..];
+        if rest.is_empty() {
+            if pending.is_some() || ctx.document[delimiter.view.end as usize..].contains('\n') {
+                // The input ends within the body, where nothing can be completed.
+                ctx.completion.closed = true;
+            }
+            ctx.warn(delimiter.range, "Unterminated here-document");
+            break (offset, offset);
+        }
+        let width = rest.find('\n').map_or(rest.len(), |index| index + 1);
+        let line = rest[..width].trim_end_matches('\n');
+        if (if strip_tabs { line.trim_start_matches('\t') } else { line }) == delimiter_text {
+            break (offset, offset + width);
+        }
+        offset += width;
+    };
+
+    let line = ctx.document[..start].matches('\n').count() as u32;
+    let end_line = line + ctx.document[start..body_end].matches('\n').count() as u32;
+    let body = db::Location {
+        range: lsp::Range {
+            start: lsp::Position { line, character: 0 },
+            end: lsp::Position { line: end_line, character: 0 },
+        },
+        view: db::View { start: start as u32, end: body_end as u32 },
+    };
+
+    let expansions = if quoted { Vec::new() } else { extract_heredoc_expansions(ctx, body) };
+    string_tokens(ctx, line, body.view.string(ctx.document), &expansions);
+
+    let delimiter_line = end_line + ctx.document[body_end..region_end].matches('\n').count() as u32;
+    ctx.info.heredocs.push(lsp::Range {
+        start: body.range.start,
+        end: lsp::Position { line: delimiter_line, character: 0 },
+    });
+
+    let region_start = pending.map_or(start as u32, |region| region.start);
+    ctx.lexer.skip_region(db::View { start: region_start, end: region_end as u32 });
+}
+
 fn skip_redirect(ctx: &mut Context) {
-    while ctx.lexer.next_if(kind_matches(REDIRECT_KINDS)).is_some() {
+    while let Some(redirect) = ctx.lexer.next_if(kind_matches(REDIRECT_KINDS)) {
+        ctx.token(redirect.range, lsp::SemanticTokenKind::Operator);
+        let heredoc = match redirect.kind {
+            TokenKind::LessLess => match ctx.lexer.next_if_kind(TokenKind::Less) {
+                Some(less) => {
+                    ctx.token(less.range, lsp::SemanticTokenKind::Operator);
+                    let range = lsp::Range { start: redirect.range.start, end: less.range.end };
+                    add_bashism(ctx, db::BashismKind::HereString, range, None);
+                    false
+                }
+                None => true,
+            },
+            TokenKind::LessLessDash => true,
+            _ => false,
+        };
         skip_whitespace(ctx);
-        if !protected(ctx, parse_value) {
-            let diagnostic = ctx.expected("a filename");
-            ctx.emit(diagnostic);
+        let position = if heredoc {
+            CompletionPosition::HeredocDelimiter
+        }
+        else {
+            CompletionPosition::Redirect
+        };
+        ctx.complete(position, false);
+        match parse_argument(ctx) {
+            Ok(Some(delimiter)) if heredoc => {
+                extract_heredoc(ctx, delimiter, redirect.kind == TokenKind::LessLessDash);
+            }
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                let diagnostic = ctx.expected("a filename");
+                ctx.emit(diagnostic);
+            }
+            Err(diagnostic) => ctx.emit(diagnostic),
         }
         skip_whitespace(ctx);
     }
 }
 
-fn extract_arguments_until(ctx: &mut Context, end: impl Copy + Fn(Token) -> bool) {
+/// Extract the arguments of `command`, which is `None` for the words of compound commands.
+fn extract_arguments_until(
+    ctx: &mut Context,
+    command: Option<Token>,
+    end: impl Copy + Fn(Token) -> bool,
+) -> Vec<db::Location> {
+    let mut arguments = Vec::new();
     loop {
         skip_whitespace(ctx);
         skip_redirect(ctx);
-        if ctx.lexer.peek().is_none_or(end) || !protected(ctx, parse_value) {
+        if ctx.at_end() {
+            let command = command.map(|word| lex::escape(word.view.string(ctx.document)).into());
+            ctx.complete(CompletionPosition::Argument { command }, false);
+        }
+        if ctx.lexer.peek().is_none_or(end) {
             break;
         }
+        let outer = std::mem::replace(&mut ctx.argument_expansions, command.map(|_| Vec::new()));
+        let result = parse_argument(ctx);
+        let expansions = std::mem::replace(&mut ctx.argument_expansions, outer);
+        for expansion in expansions.into_iter().flatten() {
+            quote_expansion(ctx, expansion);
+        }
+        match result {
+            Ok(Some(argument)) => arguments.push(argument),
+            Ok(None) => break,
+            Err(diagnostic) => {
+                ctx.emit(diagnostic);
+                break;
+            }
+        }
+    }
+    arguments
+}
+
+/// Restrict `word` to the parameter name at its start, as in `$name/rest`.
+fn parameter_name(document: &str, word: Token) -> Token {
+    let text = word.view.string(document);
+    let width = match text.chars().next() {
+        Some('?' | '-' | '@' | '*' | '!') => 1,
+        Some(char) if char.is_ascii_digit() => {
+            text.find(|char: char| !char.is_ascii_digit()).unwrap_or(text.len())
+        }
+        _ => text.find(|char: char| !char.is_alphanumeric() && char != '_').unwrap_or(text.len()),
+    };
+    if width == 0 || width == text.len() {
+        return word;
+    }
+    let end = word.range.start.horizontal_offset(text[..width].chars().count() as u32);
+    Token {
+        view: db::View { start: word.view.start, end: word.view.start + width as u32 },
+        range: lsp::Range { start: word.range.start, end },
+        ..word
     }
 }
 
 fn extract_potential_expansion(dollar: Token, ctx: &mut Context) -> ParseResult<()> {
+    // Expansions nested in this one are not command arguments by themselves.
+    let expansions = ctx.argument_expansions.take();
+    let result = extract_expansion(dollar, ctx);
+    ctx.argument_expansions = expansions;
+    if result? && ctx.quoting == db::Quoting::Unquoted {
+        let last = ctx.lexer.previous().unwrap_or(dollar);
+        if let Some(expansions) = &mut ctx.argument_expansions {
+            expansions.push(location(dollar, last));
+        }
+    }
+    Ok(())
+}
+
+/// Extract the expansion after `dollar`, and return whether its result is subject to word
+/// splitting when unquoted.
+fn extract_expansion(dollar: Token, ctx: &mut Context) -> ParseResult<bool> {
+    let unquoted = ctx.quoting == db::Quoting::Unquoted;
+    let is_ansi_c_string = |token: Token| token.kind == TokenKind::RawString && unquoted;
     if let Some(word) = ctx.lexer.next_if_kind(TokenKind::Word) {
-        add_var_read(ctx, word);
+        if word.view.string(ctx.document).starts_with('[') {
+            extract_deprecated_arithmetic(ctx, dollar, word)?;
+            Ok(false)
+        }
+        else {
+            let name = parameter_name(ctx.document, word);
+            add_var_read(ctx, name);
+            Ok(!["?", "!", "-"].contains(&name.view.string(ctx.document)))
+        }
     }
     else if ctx.consume(TokenKind::BraceOpen) {
         let name = ctx.expect(TokenKind::Word)?;
-        add_var_read(ctx, name);
+        add_var_read(ctx, parameter_name(ctx.document, name));
+        // The operand of an operator, as in `${name:-word}`, may contain further expansions.
+        while parse_value(ctx)? || ctx.consume(TokenKind::Space) {}
         ctx.expect(TokenKind::BraceClose)?;
+        Ok(true)
     }
     else if ctx.consume(TokenKind::ParenOpen) {
+        let arithmetic = ctx.lexer.peek().is_some_and(|token| token.kind == TokenKind::ParenOpen);
         extract_enclosed_statements(ctx, kind_matches(&[TokenKind::ParenClose]));
         ctx.expect(TokenKind::ParenClose)?;
+        Ok(!arithmetic)
     }
-    else if !ctx.consume(TokenKind::Dollar) {
-        ctx.inform(dollar.range, "This `$` is literal. Use `\\$` to suppress this hint.")
+    else {
+        if let Some(string) = ctx.lexer.next_if(is_ansi_c_string) {
+            extract_ansi_c_string(ctx, dollar, string);
+        }
+        else if !ctx.consume(TokenKind::Dollar) {
+            ctx.inform(dollar.range, "This `$` is literal. Use `\\$` to suppress this hint.")
+        }
+        Ok(false)
     }
+}
+
+/// Extract a deprecated arithmetic expansion, as in `$[1 + 2]`, which starts with `open`.
+fn extract_deprecated_arithmetic(ctx: &mut Context, dollar: Token, open: Token) -> ParseResult<()> {
+    let mut close = open;
+    while !close.view.string(ctx.document).ends_with(']') {
+        if !(ctx.consume(TokenKind::Space) || parse_value(ctx)?) {
+            return Err(ctx.expected("`]`"));
+        }
+        close = ctx.lexer.previous().unwrap_or(close);
+    }
+    let end = close.range.end;
+    let bracket = lsp::Position { character: end.character - 1, ..end };
+    let open = lsp::Range { start: dollar.range.start, end: open.range.start.horizontal_offset(1) };
+    let edits = vec![
+        lsp::TextEdit { range: open, new_text: String::from("$((") },
+        lsp::TextEdit { range: lsp::Range { start: bracket, end }, new_text: String::from("))") },
+    ];
+    let range = lsp::Range { start: dollar.range.start, end: close.range.end };
+    let diagnostic = lsp::Diagnostic::info(range, "`$[...]` is deprecated, use `$((...))` instead");
+    add_fix(ctx, 2007, diagnostic, Some(("Use `$((...))`", edits)));
     Ok(())
 }
 
+/// Report a backquoted command substitution, which can be written as `$(...)` if it contains no
+/// backslashes, since those are interpreted differently.
+fn replace_backquotes(ctx: &mut Context, open: Token, close: Token) {
+    let contents = db::View { start: open.view.end, end: close.view.start };
+    let fix = (!contents.string(ctx.document).contains('\\')).then(|| {
+        let edits = vec![
+            lsp::TextEdit { range: open.range, new_text: String::from("$(") },
+            lsp::TextEdit { range: close.range, new_text: String::from(")") },
+        ];
+        ("Use `$(...)`", edits)
+    });
+    let range = location(open, close).range;
+    let diagnostic = lsp::Diagnostic::info(range, "Use `$(...)` instead of legacy backquotes");
+    add_fix(ctx, 2006, diagnostic, fix);
+}
+
+/// Report an unquoted expansion in a command argument, which is split into words and expanded as
+/// a pathname pattern.
+fn quote_expansion(ctx: &mut Context, expansion: db::Location) {
+    let text = expansion.view.string(ctx.document);
+    let code = match text {
+        _ if text.starts_with("$(") => 2046,
+        "$@" | "${@}" => 2068,
+        "$*" | "${*}" => 2048,
+        _ if text.ends_with("[@]}") => 2068,
+        _ if text.ends_with("[*]}") => 2048,
+        _ => 2086,
+    };
+    let edit = lsp::TextEdit { range: expansion.range, new_text: format!("\"{text}\"") };
+    let message = "Quote this expansion to prevent word splitting and pathname expansion";
+    let diagnostic = lsp::Diagnostic::info(expansion.range, message);
+    add_fix(ctx, code, diagnostic, Some(("Quote the expansion", vec![edit])));
+}
+
+/// Record a `$'...'` string, which can be rewritten as a single-quoted string.
+fn extract_ansi_c_string(ctx: &mut Context, dollar: Token, string: Token) {
+    let text = string.view.string(ctx.document);
+    let range = lsp::Range { start: dollar.range.start, end: string.range.end };
+    let rewrite = lex::ansi_c_unescape(&text[1..text.len() - 1]).map(|literal| {
+        let new_text = format!("'{}'", lex::quote(&literal, Some('\'')));
+        ("Use a single-quoted string", vec![lsp::TextEdit { range, new_text }])
+    });
+    add_bashism(ctx, db::BashismKind::AnsiCString, range, rewrite);
+}
+
 fn parse_string(ctx: &mut Context, quote: Token) {
+    let quoting = std::mem::replace(&mut ctx.quoting, db::Quoting::DoubleQuotes);
+    extract_string_contents(ctx, quote);
+    ctx.quoting = quoting;
+}
+
+fn extract_string_contents(ctx: &mut Context, quote: Token) {
     while let Some(token) = ctx.lexer.next() {
         match token.kind {
             TokenKind::DoubleQuote => return,
@@ -454,7 +1118,10 @@ fn parse_string(ctx: &mut Context, quote: Token) {
                     ctx,
                     kind_matches(&[TokenKind::BackQuote, TokenKind::DoubleQuote]),
                 );
-                if !ctx.consume(TokenKind::BackQuote) {
+                if let Some(close) = ctx.lexer.next_if_kind(TokenKind::BackQuote) {
+                    replace_backquotes(ctx, token, close);
+                }
+                else {
                     let diagnostic = ctx.expected("A closing backquote");
                     ctx.emit(diagnostic);
                 }
@@ -469,10 +1136,10 @@ fn extract_conditional(ctx: &mut Context) -> ParseResult<()> {
     extract_statement(ctx)?;
     ctx.expect_word("then")?;
     extract_statements_until(ctx, |token| is_keyword(ctx.document, token, &["fi", "else", "elif"]));
-    if parse_keyword(ctx, "else") {
+    if parse_reserved_word(ctx, "else") {
         extract_statements_until(ctx, |token| is_keyword(ctx.document, token, &["fi"]));
     }
-    if parse_keyword(ctx, "elif") {
+    if parse_reserved_word(ctx, "elif") {
         return extract_conditional(ctx);
     }
     ctx.expect_word("fi")?;
@@ -487,12 +1154,13 @@ fn extract_loop_body(ctx: &mut Context) -> ParseResult<()> {
 }
 
 fn extract_for_loop(ctx: &mut Context) -> ParseResult<()> {
+    ctx.complete(CompletionPosition::VariableName, false);
     let variable = ctx.expect(TokenKind::Word)?;
-    add_var_assign(ctx, variable);
+    add_var_assign(ctx, variable, None);
     skip_whitespace(ctx);
     ctx.expect_word("in")?;
     skip_whitespace(ctx);
-    extract_arguments_until(ctx, kind_matches(END_KINDS));
+    extract_arguments_until(ctx, None, kind_matches(END_KINDS));
     expect_statement_end(ctx)?;
     extract_loop_body(ctx)?;
     Ok(())
@@ -508,7 +1176,7 @@ fn parse_pattern(ctx: &mut Context) -> ParseResult<bool> {
     skip_whitespace(ctx);
     if parse_value(ctx)? {
         skip_whitespace(ctx);
-        if ctx.consume(TokenKind::Pipe) { parse_pattern(ctx) } else { Ok(true) }
+        if parse_operator(ctx, TokenKind::Pipe) { parse_pattern(ctx) } else { Ok(true) }
     }
     else {
         Ok(false)
@@ -524,6 +1192,7 @@ fn parse_case_item(ctx: &mut Context) -> ParseResult<bool> {
         return Ok(false);
     }
     let open = ctx.consume(TokenKind::ParenOpen);
+    ctx.complete(CompletionPosition::CasePattern, !open);
     if !parse_pattern(ctx)? {
         return if open { Err(ctx.expected("a pattern")) } else { Ok(false) };
     }
@@ -532,7 +1201,49 @@ fn parse_case_item(ctx: &mut Context) -> ParseResult<bool> {
     Ok(true)
 }
 
+/// Extract a Bash conditional expression, as in `[[ -n $x && $y ]]`. It is rewritten with `[`
+/// only if the expression means the same thing there.
+fn extract_double_bracket(ctx: &mut Context, open: Token) -> ParseResult<()> {
+    const OPERATOR_KINDS: &[TokenKind] = {
+        use TokenKind::*;
+        &[AndAnd, PipePipe, ParenOpen, ParenClose, Less, Great]
+    };
+    ctx.token(open.range, lsp::SemanticTokenKind::Keyword);
+    let mut portable = true;
+    let close = loop {
+        skip_whitespace(ctx);
+        if let Some(close) = ctx.lexer.next_if(|token| is_keyword(ctx.document, token, &["]]"])) {
+            break close;
+        }
+        if let Some(operator) = ctx.lexer.next_if(kind_matches(OPERATOR_KINDS)) {
+            ctx.token(operator.range, lsp::SemanticTokenKind::Operator);
+            portable = false;
+        }
+        else if let Some(argument) = parse_argument(ctx)? {
+            // Patterns and unquoted expansions would be split and expanded as globs by `[`, and
+            // the right side of `=` and `!=` is a pattern in `[[` but a string in `[`.
+            let text = argument.view.string(ctx.document);
+            portable &= !["==", "=~"].contains(&text) && !lex::contains_unquoted(text, "$`*?[");
+        }
+        else {
+            return Err(ctx.expected("`]]`"));
+        }
+    };
+    ctx.token(close.range, lsp::SemanticTokenKind::Keyword);
+    skip_whitespace(ctx);
+    let rewrite = portable.then(|| {
+        let edits = vec![
+            lsp::TextEdit { range: open.range, new_text: String::from("[") },
+            lsp::TextEdit { range: close.range, new_text: String::from("]") },
+        ];
+        ("Use `[` instead of `[[`", edits)
+    });
+    add_bashism(ctx, db::BashismKind::DoubleBracket, location(open, close).range, rewrite);
+    Ok(())
+}
+
 fn extract_case(ctx: &mut Context) -> ParseResult<()> {
+    ctx.complete(CompletionPosition::Argument { command: None }, false);
     if !parse_value(ctx)? {
         return Err(ctx.expected("a word"));
     }
@@ -543,21 +1254,62 @@ fn extract_case(ctx: &mut Context) -> ParseResult<()> {
         let diagnostic = ctx.expected("at least one pattern");
         ctx.emit(diagnostic);
     }
-    while ctx.consume(TokenKind::SemiSemi) && protected(ctx, parse_case_item) {}
+    while parse_operator(ctx, TokenKind::SemiSemi) && protected(ctx, parse_case_item) {}
     skip_empty_lines(ctx);
     ctx.expect_word("esac")?;
     Ok(())
 }
 
+/// Parse the value of an assignment, which may be an array as in `name=(a b)`. Arrays have no
+/// single value.
+fn parse_assigned_value(ctx: &mut Context) -> ParseResult<Option<db::Location>> {
+    let Some(open) = ctx.lexer.next_if_kind(TokenKind::ParenOpen) else {
+        return parse_argument(ctx);
+    };
+    loop {
+        skip_empty_lines(ctx);
+        if !parse_value(ctx)? {
+            break;
+        }
+    }
+    let close = ctx.expect(TokenKind::ParenClose)?;
+    add_bashism(ctx, db::BashismKind::Array, location(open, close).range, None);
+    Ok(None)
+}
+
+/// Consume option arguments such as `-r`, and return the option characters.
+fn extract_options(ctx: &mut Context) -> String {
+    let mut options = String::new();
+    let is_option = |token: Token| {
+        token.kind == TokenKind::Word && token.view.string(ctx.document).starts_with('-')
+    };
+    while let Some(word) = ctx.lexer.next_if(is_option) {
+        options.push_str(&word.view.string(ctx.document)[1..]);
+        skip_whitespace(ctx);
+    }
+    options
+}
+
+fn mark_readonly(ctx: &mut Context, symbol: db::SymbolId) {
+    if let db::SymbolKind::Variable(id) = ctx.info.symbols[symbol].kind {
+        ctx.info.variables[id].readonly = true;
+    }
+}
+
 fn extract_builtin_local(ctx: &mut Context) -> ParseResult<()> {
     skip_whitespace(ctx);
+    let readonly = extract_options(ctx).contains('r');
+    ctx.complete(CompletionPosition::VariableName, false);
     while let Some(word) = ctx.lexer.next_if_kind(TokenKind::Word) {
+        let value = if ctx.consume(TokenKind::Equal) { parse_assigned_value(ctx)? } else { None };
         if let Some(function) = &mut ctx.function {
             let name = lex::escape(word.view.string(ctx.document)).into_owned();
             let id = ctx.info.new_variable(name.clone(), db::Variable {
                 description: ctx.annotations.desc.take(),
                 first_assignment: Some(db::Location { range: word.range, view: word.view }),
+                value,
                 kind: db::VariableKind::Local,
+                readonly,
             });
             ctx.info.references.push(db::SymbolReference::write(word.range, id));
             function.locals.insert(name, id);
@@ -565,22 +1317,24 @@ fn extract_builtin_local(ctx: &mut Context) -> ParseResult<()> {
         else {
             ctx.warn(word.range, "`local` is invalid outside of a function");
         }
-        if ctx.consume(TokenKind::Equal) {
-            parse_value(ctx)?;
-        }
         skip_whitespace(ctx);
+        ctx.complete(CompletionPosition::VariableName, false);
     }
     Ok(())
 }
 
-fn extract_builtin_variable_declaration(ctx: &mut Context) -> ParseResult<()> {
+fn extract_builtin_variable_declaration(ctx: &mut Context, readonly: bool) -> ParseResult<()> {
     skip_whitespace(ctx);
+    extract_options(ctx);
+    ctx.complete(CompletionPosition::VariableName, false);
     while let Some(word) = ctx.lexer.next_if_kind(TokenKind::Word) {
-        add_var_assign(ctx, word);
-        if ctx.consume(TokenKind::Equal) {
-            parse_value(ctx)?;
+        let value = if ctx.consume(TokenKind::Equal) { parse_assigned_value(ctx)? } else { None };
+        let symbol = add_var_assign(ctx, word, value);
+        if readonly {
+            mark_readonly(ctx, symbol);
         }
         skip_whitespace(ctx);
+        ctx.complete(CompletionPosition::VariableName, false);
     }
     Ok(())
 }
@@ -598,6 +1352,13 @@ fn extract_builtin_unset(ctx: &mut Context) -> ParseResult<()> {
     else {
         false
     };
+    let position = if is_function {
+        CompletionPosition::FunctionName
+    }
+    else {
+        CompletionPosition::VariableName
+    };
+    ctx.complete(position.clone(), false);
     while let Some(word) = ctx.lexer.next_if_kind(TokenKind::Word) {
         if is_function {
             unset_function(ctx, word);
@@ -606,6 +1367,7 @@ fn extract_builtin_unset(ctx: &mut Context) -> ParseResult<()> {
             add_var_write(ctx, word);
         }
         skip_whitespace(ctx);
+        ctx.complete(position.clone(), false);
     }
     Ok(())
 }
@@ -639,7 +1401,28 @@ fn make_function_state(ctx: &mut Context, fun_id: db::FunctionId) -> FunctionSta
     state
 }
 
-fn extract_function(ctx: &mut Context, word: Token) -> ParseResult<()> {
+/// Extract a function definition that starts with the `function` keyword, as in `function name {`.
+fn extract_function_keyword(ctx: &mut Context, keyword: Token) -> ParseResult<()> {
+    ctx.token(keyword.range, lsp::SemanticTokenKind::Keyword);
+    let word = ctx.expect(TokenKind::Word)?;
+    skip_whitespace(ctx);
+    let parenthesized = ctx.consume(TokenKind::ParenOpen);
+    let edit = if parenthesized {
+        let range = lsp::Range { start: keyword.range.start, end: word.range.start };
+        lsp::TextEdit { range, new_text: String::new() }
+    }
+    else {
+        let range = lsp::Range { start: keyword.range.start, end: word.range.end };
+        lsp::TextEdit { range, new_text: format!("{}()", word.view.string(ctx.document)) }
+    };
+    let rewrite = Some(("Use a POSIX function definition", vec![edit]));
+    add_bashism(ctx, db::BashismKind::FunctionKeyword, keyword.range, rewrite);
+    extract_function(ctx, word, parenthesized)
+}
+
+/// Extract a function definition. The opening parenthesis after the name has been consumed if
+/// `parenthesized` is set.
+fn extract_function(ctx: &mut Context, word: Token, parenthesized: bool) -> ParseResult<()> {
     if !is_identifier(word.view.string(ctx.document), ctx.info.shell) {
         ctx.warn(word.range, "Invalid function name");
     }
@@ -648,11 +1431,13 @@ fn extract_function(ctx: &mut Context, word: Token) -> ParseResult<()> {
     let fun_id = function_id(ctx, sym_id).expect("should be a function");
 
     let state = make_function_state(ctx, fun_id);
-    let previous = std::mem::replace(&mut ctx.function, Some(state));
+    let previous = ctx.function.replace(state);
 
     let result = (|| {
         skip_whitespace(ctx);
-        ctx.expect(TokenKind::ParenClose)?;
+        if parenthesized {
+            ctx.expect(TokenKind::ParenClose)?;
+        }
         skip_empty_lines(ctx);
         ctx.expect(TokenKind::BraceOpen)?;
         skip_empty_lines(ctx);
@@ -666,16 +1451,58 @@ fn extract_function(ctx: &mut Context, word: Token) -> ParseResult<()> {
     result
 }
 
+/// Report `==` in `[` or `test`, which is not portable.
+fn check_test_operators(ctx: &mut Context, arguments: &[db::Location]) {
+    for argument in arguments {
+        if argument.view.string(ctx.document) == "==" {
+            let edit = lsp::TextEdit { range: argument.range, new_text: String::from("=") };
+            let message = "`==` is not specified by POSIX, use `=` instead";
+            let diagnostic = lsp::Diagnostic::warning(argument.range, message);
+            add_fix(ctx, 3014, diagnostic, Some(("Use `=`", vec![edit])));
+        }
+    }
+}
+
+/// Report `echo -e`, which is not portable. It is replaced with `printf` if there is one argument,
+/// since `printf` would format multiple arguments separately.
+fn check_echo_options(ctx: &mut Context, echo: Token, arguments: &[db::Location]) {
+    let Some(flag) = arguments.first() else { return };
+    let text = flag.view.string(ctx.document);
+    if !["-e", "-en", "-ne"].contains(&text) {
+        return;
+    }
+    let fix = (arguments.len() == 2).then(|| {
+        let format = if text.contains('n') { "'%b'" } else { "'%b\\n'" };
+        let range = lsp::Range { start: echo.range.start, end: flag.range.end };
+        ("Use `printf`", vec![lsp::TextEdit { range, new_text: format!("printf {format}") }])
+    });
+    let message = "`echo` options are not specified by POSIX, use `printf` instead";
+    add_fix(ctx, 3037, lsp::Diagnostic::warning(flag.range, message), fix);
+}
+
+/// Record a call, and report non-portable uses of `[` and `echo` in POSIX shell scripts.
+fn add_call(ctx: &mut Context, word: Token, id: db::SymbolId, arguments: Vec<db::Location>) {
+    if ctx.info.shell == Shell::Posix {
+        match word.view.string(ctx.document) {
+            "[" | "test" => check_test_operators(ctx, &arguments),
+            "echo" => check_echo_options(ctx, word, &arguments),
+            _ => {}
+        }
+    }
+    ctx.info.calls.push(db::Call { name: location(word, word), id, arguments });
+}
+
 fn extract_line_command(
     ctx: &mut Context,
     word: Token,
     end: impl Copy + Fn(Token) -> bool,
 ) -> ParseResult<()> {
     if ctx.consume(TokenKind::Equal) {
-        parse_value(ctx)?;
+        let value = parse_assigned_value(ctx)?;
         skip_whitespace(ctx);
+        ctx.complete(CompletionPosition::Command, false);
         if ctx.lexer.peek().is_none_or(end) {
-            add_var_assign(ctx, word);
+            add_var_assign(ctx, word, value);
         }
         else {
             let word = ctx.expect(TokenKind::Word)?;
@@ -685,26 +1512,28 @@ fn extract_line_command(
     }
     else {
         let command = lex::escape(word.view.string(ctx.document));
+        if command == "local" {
+            add_bashism(ctx, db::BashismKind::Local, word.range, None);
+        }
         if let Some(&id) = ctx.commands.get(command.as_ref()) {
             if matches!(ctx.info.symbols[id].kind, db::SymbolKind::Builtin) {
-                ctx.info.tokens.data.push(lsp::SemanticToken {
-                    position: word.range.start,
-                    width: word.range.end.character - word.range.start.character,
-                    kind: lsp::SemanticTokenKind::Keyword,
-                    modifier: lsp::SemanticTokenModifier::None,
-                });
                 ctx.info.references.push(db::SymbolReference::read(word.range, id));
                 match command.as_ref() {
-                    "export" | "readonly" => extract_builtin_variable_declaration(ctx)?,
+                    "export" => extract_builtin_variable_declaration(ctx, false)?,
+                    "readonly" => extract_builtin_variable_declaration(ctx, true)?,
                     "unset" => extract_builtin_unset(ctx)?,
                     "local" => extract_builtin_local(ctx)?,
-                    _ => extract_arguments_until(ctx, end),
+                    _ => {
+                        let arguments = extract_arguments_until(ctx, Some(word), end);
+                        add_call(ctx, word, id, arguments);
+                    }
                 }
                 return Ok(());
             }
         }
-        add_cmd_ref(ctx, word);
-        extract_arguments_until(ctx, end);
+        let id = add_cmd_ref(ctx, word);
+        let arguments = extract_arguments_until(ctx, Some(word), end);
+        add_call(ctx, word, id, arguments);
     }
     Ok(())
 }
@@ -715,7 +1544,7 @@ fn extract_command(
     end: impl Copy + Fn(Token) -> bool,
 ) -> ParseResult<()> {
     if ctx.consume(TokenKind::ParenOpen) {
-        extract_function(ctx, word)
+        extract_function(ctx, word, true)
     }
     else {
         extract_line_command(ctx, word, end)
@@ -730,20 +1559,31 @@ fn extract_statement_up_to(
         end(token) || token.kind == TokenKind::NewLine || kind_matches(CONTINUATION_KINDS)(token)
     };
     skip_empty_lines(ctx);
+    skip_whitespace(ctx);
+    if let Some(token) = ctx.lexer.peek() {
+        ctx.info.statement_starts.push(token.range.start);
+    }
     loop {
         skip_whitespace(ctx);
+        ctx.complete(CompletionPosition::Command, false);
         if let Some(word) = ctx.lexer.next_if_kind(TokenKind::Word) {
             skip_whitespace(ctx);
-            match word.view.string(ctx.document) {
+            let keyword = word.view.string(ctx.document);
+            if ["if", "for", "while", "case"].contains(&keyword) {
+                ctx.token(word.range, lsp::SemanticTokenKind::Keyword);
+            }
+            match keyword {
                 "if" => extract_conditional(ctx)?,
                 "for" => extract_for_loop(ctx)?,
                 "while" => extract_while_loop(ctx)?,
                 "case" => extract_case(ctx)?,
+                "[[" => extract_double_bracket(ctx, word)?,
+                "function" => extract_function_keyword(ctx, word)?,
                 _ => extract_command(ctx, word, end)?,
             }
         }
         else if parse_value(ctx)? {
-            extract_arguments_until(ctx, end);
+            extract_arguments_until(ctx, None, end);
         }
         else if ctx.consume(TokenKind::ParenOpen) {
             skip_whitespace(ctx);
@@ -758,15 +1598,34 @@ fn extract_statement_up_to(
         else {
             return Err(ctx.expected("a statement"));
         }
-        if ctx.lexer.next_if(kind_matches(CONTINUATION_KINDS)).is_none() {
-            return Ok(());
+        match ctx.lexer.next_if(kind_matches(CONTINUATION_KINDS)) {
+            Some(token) => {
+                ctx.token(token.range, lsp::SemanticTokenKind::Operator);
+                // The pipeline or list continues on the next line after `|`, `&&`, or `||`.
+                if token.kind != TokenKind::And {
+                    skip_empty_lines(ctx);
+                }
+            }
+            None => return Ok(()),
         }
     }
 }
 
 fn extract_statement(ctx: &mut Context) -> ParseResult<()> {
     skip_whitespace(ctx);
-    extract_statement_up_to(ctx, kind_matches(END_KINDS))?;
+    let directives = std::mem::take(&mut ctx.directives);
+    let start = ctx.lexer.peek().map(|token| token.range.start);
+    let result = extract_statement_up_to(ctx, kind_matches(END_KINDS));
+    if let Some((start, previous)) = start.zip(ctx.lexer.previous()) {
+        let scope = lsp::Range { start, end: previous.range.end };
+        for Directive { range, codes } in directives {
+            if !codes.is_empty() {
+                let suppression = db::Suppression { directive: range, codes, scope };
+                ctx.info.suppressions.push(suppression);
+            }
+        }
+    }
+    result?;
     skip_whitespace(ctx);
     expect_statement_end(ctx)?;
     skip_whitespace(ctx);
@@ -775,7 +1634,30 @@ fn extract_statement(ctx: &mut Context) -> ParseResult<()> {
 }
 
 fn extract_statements_until(ctx: &mut Context, predicate: impl Copy + Fn(Token) -> bool) {
-    while !ctx.lexer.peek().is_none_or(predicate) {
+    loop {
+        skip_empty_lines(ctx);
+        ctx.complete(CompletionPosition::Command, true);
+        if ctx.lexer.peek().is_none_or(predicate) {
+            discard_directives(ctx);
+            break;
+        }
+        if let Err(diagnostic) = extract_statement(ctx) {
+            ctx.emit(diagnostic);
+            skip_to_next_recovery_point(ctx);
+        }
+    }
+}
+
+/// Extract the top-level statements of the document, recording where each one starts.
+fn extract_script(ctx: &mut Context) {
+    loop {
+        skip_empty_lines(ctx);
+        let Some(token) = ctx.lexer.peek()
+        else {
+            discard_directives(ctx);
+            break;
+        };
+        ctx.info.statements.push(token.range.start);
         if let Err(diagnostic) = extract_statement(ctx) {
             ctx.emit(diagnostic);
             skip_to_next_recovery_point(ctx);
@@ -795,8 +1677,17 @@ fn skip_to_next_recovery_point(ctx: &mut Context) {
 fn parse_shebang(ctx: &mut Context) {
     if let Some(comment) = ctx.lexer.next_if_kind(TokenKind::Comment) {
         if let Some(shebang) = comment.view.string(ctx.document).strip_prefix("#!") {
+            ctx.token(comment.range, lsp::SemanticTokenKind::Comment);
             match shell::parse_shebang(shebang) {
-                Ok(shell) => ctx.info.shell = shell,
+                Ok(shell) => {
+                    ctx.info.shell = shell;
+                    ctx.interpreter = shell::shebang_interpreter(shebang).ok().map(|range| {
+                        let start = 2 + shebang[..range.start].chars().count() as u32;
+                        let width = shebang[range].chars().count() as u32;
+                        let start = comment.range.start.horizontal_offset(start);
+                        lsp::Range { start, end: start.horizontal_offset(width) }
+                    });
+                }
                 Err(error) => ctx.warn(comment.range, error),
             }
         }
@@ -813,6 +1704,70 @@ fn collect_references(info: &mut db::DocumentInfo) {
     }
 }
 
+fn symbol_token_kind(
+    info: &db::DocumentInfo,
+    kind: db::SymbolKind,
+) -> Option<(lsp::SemanticTokenKind, lsp::SemanticTokenModifiers)> {
+    use lsp::SemanticTokenKind as Kind;
+    use lsp::SemanticTokenModifier::*;
+    let none = lsp::SemanticTokenModifiers::NONE;
+    match kind {
+        db::SymbolKind::Variable(id) => {
+            let variable = &info.variables[id];
+            let mut modifiers = none;
+            if matches!(variable.kind, db::VariableKind::Environment) {
+                modifiers = modifiers.with(DefaultLibrary);
+            }
+            if variable.readonly {
+                modifiers = modifiers.with(Readonly);
+            }
+            Some((Kind::Variable, modifiers))
+        }
+        db::SymbolKind::Function(_) => Some((Kind::Function, none)),
+        db::SymbolKind::Parameter(_) => Some((Kind::Parameter, none)),
+        db::SymbolKind::Special(_) => Some((Kind::Parameter, none.with(DefaultLibrary))),
+        db::SymbolKind::Command => Some((Kind::Function, none.with(DefaultLibrary))),
+        db::SymbolKind::Builtin => Some((Kind::Keyword, none.with(DefaultLibrary))),
+        db::SymbolKind::Error => None,
+    }
+}
+
+/// Emit semantic tokens for symbol references. The first write to a symbol is
+/// its declaration, and subsequent writes are modifications.
+fn symbol_tokens(info: &mut db::DocumentInfo) {
+    for symbol in &info.symbols.underlying {
+        let Some((kind, modifiers)) = symbol_token_kind(info, symbol.kind) else { continue };
+        let mut declared = false;
+        for &index in &symbol.ref_indices {
+            let reference = info.references[index as usize].reference;
+            let modifiers = match reference.kind {
+                lsp::ReferenceKind::Read => modifiers,
+                lsp::ReferenceKind::Write if declared => {
+                    modifiers.with(lsp::SemanticTokenModifier::Modification)
+                }
+                lsp::ReferenceKind::Write => {
+                    declared = true;
+                    modifiers.with(lsp::SemanticTokenModifier::Declaration)
+                }
+            };
+            info.tokens.data.push(lsp::SemanticToken::new(reference.range, kind, modifiers));
+        }
+    }
+}
+
+/// Sort the semantic tokens by position and discard empty or overlapping tokens.
+fn sort_tokens(tokens: &mut Vec<lsp::SemanticToken>) {
+    tokens.sort_by_key(|token| token.position);
+    let mut end = lsp::Position::default();
+    tokens.retain(|token| {
+        let retain = token.width != 0 && end <= token.position;
+        if retain {
+            end = token.position.horizontal_offset(token.width);
+        }
+        retain
+    });
+}
+
 fn executables(dirs: &[std::path::PathBuf]) -> Vec<String> {
     let mut names: Vec<String> = dirs.iter().flat_map(|dir| env::executable_names(dir)).collect();
     names.sort_unstable();
@@ -837,6 +1792,10 @@ fn prepare_environment(ctx: &mut Context, settings: &Settings) {
             }
         }
     }
+    prepare_builtins(ctx);
+}
+
+fn prepare_builtins(ctx: &mut Context) {
     for name in shell::builtins(ctx.info.shell).iter().copied().map(String::from) {
         let symbol = ctx.info.symbols.push(db::Symbol::new(name.clone(), db::SymbolKind::Builtin));
         ctx.commands.insert(name, symbol);
@@ -850,13 +1809,25 @@ pub fn parse(input: &str, settings: &Settings) -> db::DocumentInfo {
     let mut ctx = Context::new(input, settings.default_shell);
     parse_shebang(&mut ctx);
     prepare_environment(&mut ctx, settings);
-    skip_empty_lines(&mut ctx);
-    extract_statements_until(&mut ctx, |_| false);
+    extract_script(&mut ctx);
     collect_references(&mut ctx.info);
+    symbol_tokens(&mut ctx.info);
+    sort_tokens(&mut ctx.info.tokens.data);
     ctx.info
 }
 
-fn add_var_assign(ctx: &mut Context, word: Token) {
+/// Parse `input`, which ends where a word is about to be completed, and determine what could
+/// follow it. The environment is not inspected.
+pub fn completion_context(input: &str, shell: Shell) -> CompletionContext {
+    let mut ctx = Context::new(input, shell);
+    parse_shebang(&mut ctx);
+    prepare_builtins(&mut ctx);
+    skip_empty_lines(&mut ctx);
+    extract_statements_until(&mut ctx, |_| false);
+    ctx.completion.context
+}
+
+fn add_var_assign(ctx: &mut Context, word: Token, value: Option<db::Location>) -> db::SymbolId {
     let sym_id = add_var_write(ctx, word);
     match ctx.info.symbols[sym_id].kind {
         db::SymbolKind::Variable(var_id) => {
@@ -864,6 +1835,7 @@ fn add_var_assign(ctx: &mut Context, word: Token) {
             if var.first_assignment.is_none() {
                 var.first_assignment = Some(db::Location { range: word.range, view: word.view });
                 var.description = ctx.annotations.desc.take();
+                var.value = value;
             }
         }
         _ => {
@@ -873,6 +1845,7 @@ fn add_var_assign(ctx: &mut Context, word: Token) {
             );
         }
     }
+    sym_id
 }
 
 #[cfg(test)]
@@ -883,6 +1856,13 @@ mod tests {
         super::parse(input, &Settings::default()).diagnostics
     }
 
+    /// Diagnostics other than those of the problems that Shellcheck would also report.
+    fn unexpected_diagnostics(input: &str) -> Vec<crate::lsp::Diagnostic> {
+        let mut diagnostics = diagnostics(input);
+        diagnostics.retain(|diagnostic| !super::NATIVE_CODES.contains(&diagnostic.code));
+        diagnostics
+    }
+
     #[test]
     fn conditional() {
         assert!(diagnostics("if ls -la; then\n\tpwd\n\tuname -a\nfi\n").is_empty());
@@ -890,12 +1870,12 @@ mod tests {
 
     #[test]
     fn for_loop() {
-        assert!(diagnostics("for x in a b c\ndo\n\techo $x\ndone\n").is_empty());
+        assert!(unexpected_diagnostics("for x in a b c\ndo\n\techo $x\ndone\n").is_empty());
     }
 
     #[test]
     fn while_loop() {
-        assert!(diagnostics("while true; do echo $x; done\n").is_empty());
+        assert!(unexpected_diagnostics("while true; do echo $x; done\n").is_empty());
     }
 
     #[test]
@@ -903,6 +1883,95 @@ mod tests {
         assert!(diagnostics("a=b c=d e f\n").is_empty());
     }
 
+    #[test]
+    fn heredoc() {
+        assert!(diagnostics("cat <<EOF\n) not parsed (\nEOF\necho\n").is_empty());
+        assert!(diagnostics("cat <<-'A' <<B; echo\n\t) (\n\tA\n) (\nB\n").is_empty());
+        assert!(diagnostics("#!/bin/bash\ncat <<<word\n").is_empty());
+    }
+
+    #[test]
+    fn bashisms() {
+        use crate::db::BashismKind::*;
+        let input = "a=(b c)\nfunction f { local x; }\n[[ -n \"$a\" ]] && echo $'\\t' <<<x\n";
+        let kinds = |input: &str| {
+            let info = super::parse(input, &Settings::default());
+            (info.bashisms.iter().map(|bashism| bashism.kind).collect::<Vec<_>>(), info)
+        };
+        let (found, info) = kinds(input);
+        assert_eq!(found, [Array, FunctionKeyword, Local, DoubleBracket, AnsiCString, HereString]);
+        assert_eq!(info.diagnostics.len(), 6);
+        assert_eq!(info.actions.len(), 9);
+
+        let (found, info) = kinds(&format!("#!/bin/bash\n{input}"));
+        assert_eq!(found.len(), 6);
+        assert!(info.diagnostics.is_empty() && info.actions.is_empty());
+    }
+
+    #[test]
+    fn double_bracket_rewrite() {
+        let is_rewritten = |input: &str| {
+            let info = super::parse(input, &Settings::default());
+            (info.actions.iter()).any(|action| {
+                matches!(&action.kind, crate::db::ActionKind::Edit { title, .. }
+                    if title == "Use `[` instead of `[[`")
+            })
+        };
+        assert!(is_rewritten("[[ -n \"$x\" ]]\n"));
+        assert!(is_rewritten("[[ \"$x\" = 'foo*' ]]\n"));
+        assert!(!is_rewritten("[[ $x = foo ]]\n"));
+        assert!(!is_rewritten("[[ \"$x\" = foo* ]]\n"));
+        assert!(!is_rewritten("[[ \"$x\" != [ab] ]]\n"));
+        assert!(!is_rewritten("[[ -n a$x ]]\n"));
+        assert!(!is_rewritten("[[ \"$x\" == foo ]]\n"));
+    }
+
+    #[test]
+    fn expansion_operators() {
+        let info = super::parse("echo \"${x:-$HOME}\" ${x%/*} ${x:=a b}\n", &Settings::default());
+        assert!(info.diagnostics.iter().all(|diagnostic| diagnostic.code == 2086));
+        assert!(!info.symbols.underlying.iter().any(|symbol| symbol.name.starts_with("x:")));
+    }
+
+    #[test]
+    fn native_fixes() {
+        let codes = |input: &str| -> Vec<i32> {
+            let info = super::parse(input, &Settings::default());
+            info.diagnostics.iter().map(|diagnostic| diagnostic.code).collect()
+        };
+        assert_eq!(codes("echo $a \"$b\" x$(c) $? $((1))\n"), [2086, 2046]);
+        assert_eq!(codes("echo $@ ${*} ${a[@]}\n"), [2068, 2048, 2068]);
+        assert!(codes("a=$b; for x in $c; do :; done\n").is_empty());
+        assert_eq!(codes("echo \"`a`\" $[1 + $b]\n"), [2006, 2007]);
+        assert_eq!(codes("[ a == b ] && echo -e \"\\t\"\n"), [3014, 3037]);
+        assert!(codes("#!/bin/bash\n[ a == b ] && echo -e \"\\t\"\n").is_empty());
+    }
+
+    #[test]
+    fn shellcheck_directives() {
+        let messages = |input: &str| -> Vec<String> {
+            diagnostics(input).into_iter().map(|diagnostic| diagnostic.message).collect()
+        };
+        assert!(messages("# shellcheck disable=SC2086,2034 shell=sh\necho \"$a\"\n").is_empty());
+        assert_eq!(messages("# shellcheck disable=SC20 enable=x\n:\n"), [
+            "Invalid Shellcheck code: 'SC20'",
+            "Unknown optional check: 'x'"
+        ]);
+        assert_eq!(messages("# shellcheck foo=bar\n:\n"), ["Unknown Shellcheck directive: 'foo'"]);
+        assert_eq!(messages(":\n: # shellcheck disable=SC2086\n"), [
+            "Shellcheck directives have no effect after a command on the same line"
+        ]);
+        assert_eq!(messages(":\n# shellcheck disable=SC2086\n"), [
+            "This Shellcheck directive has no effect, because no command follows it"
+        ]);
+
+        let input = ":\n# shellcheck disable=SC2000-SC2099\nf() {\n  :\n}\n:\n";
+        let info = super::parse(input, &Settings::default());
+        let [suppression] = info.suppressions.as_slice() else { panic!() };
+        assert_eq!(suppression.codes[0].0, 2000..=2099);
+        assert_eq!((suppression.scope.start.line, suppression.scope.end.line), (2, 4));
+    }
+
     #[test]
     fn dollar() {
         if let [diag] = diagnostics("echo $\n").as_slice() {
@@ -912,4 +1981,35 @@ mod tests {
             panic!();
         }
     }
+
+    #[test]
+    fn completion_context() {
+        use super::CompletionPosition::*;
+        let context = |input| {
+            let context = super::completion_context(input, crate::shell::Shell::Posix);
+            (context.position, context.keywords)
+        };
+        let argument = |command: &str| Some(Argument { command: Some(String::from(command)) });
+        assert_eq!(context(""), (Some(Command), vec![]));
+        assert_eq!(context("a && "), (Some(Command), vec![]));
+        assert_eq!(context("echo "), (argument("echo"), vec![]));
+        assert_eq!(context("echo a >"), (Some(Redirect), vec![]));
+        assert_eq!(context("cat <<"), (Some(HeredocDelimiter), vec![]));
+        assert_eq!(context("cat <<EOF\n"), (None, vec![]));
+        assert_eq!(context("if "), (Some(Command), vec![]));
+        assert_eq!(context("if a; "), (None, vec!["then"]));
+        assert_eq!(context("if a; then\n\t"), (Some(Command), vec!["else", "elif", "fi"]));
+        assert_eq!(context("if a; then b "), (argument("b"), vec![]));
+        assert_eq!(context("if a; then echo $("), (Some(Command), vec![]));
+        assert_eq!(context("while a; do if "), (Some(Command), vec![]));
+        assert_eq!(context("while a; do b; "), (Some(Command), vec!["done"]));
+        assert_eq!(context("for "), (Some(VariableName), vec![]));
+        assert_eq!(context("for x "), (None, vec!["in"]));
+        assert_eq!(context("for x in "), (Some(Argument { command: None }), vec![]));
+        assert_eq!(context("case x in\n"), (Some(CasePattern), vec!["esac"]));
+        assert_eq!(context("case x in\na) b;;\n"), (Some(CasePattern), vec!["esac"]));
+        assert_eq!(context("case x in\na) b\n"), (Some(Command), vec!["esac"]));
+        assert_eq!(context("export a "), (Some(VariableName), vec![]));
+        assert_eq!(context("unset -f "), (Some(FunctionName), vec![]));
+    }
 }
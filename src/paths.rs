@@ -0,0 +1,394 @@
+use crate::poschars::PosChars;
+use crate::{actions, db, lex, lsp};
+use std::path::{Component, Path, PathBuf};
+
+/// Limits how many variable indirections are followed when evaluating a path.
+const MAX_DEPTH: u32 = 8;
+
+/// Ways to refer to the directory of the running script, after canonicalization.
+const SCRIPT_DIRECTORY_IDIOMS: &[&str] = &[
+    "$(dirname $0)",
+    "`dirname $0`",
+    "${0%/*}",
+    "$(cd $(dirname $0) && pwd)",
+    "$(cd $(dirname $0); pwd)",
+    "$(cd $(dirname $0) && pwd -P)",
+    "$(dirname $(realpath $0))",
+    "$(dirname $(readlink -f $0))",
+    "$(realpath $(dirname $0))",
+];
+
+fn canonicalize_idiom(str: &str) -> String {
+    let mut string = str.replace('"', "").replace("-- ", "");
+    for zero in ["BASH_SOURCE[0]", "BASH_SOURCE"] {
+        string = string.replace(zero, "0");
+    }
+    string = string.replace("${0}", "$0");
+    string.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Check whether `str` expands to the directory of the script, like `$(dirname "$0")`.
+pub fn is_script_directory(str: &str) -> bool {
+    SCRIPT_DIRECTORY_IDIOMS.contains(&canonicalize_idiom(str).as_str())
+}
+
+pub fn home() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(PathBuf::from)
+}
+
+fn absolute_position(start: lsp::Position, relative: lsp::Position) -> lsp::Position {
+    if relative.line == 0 {
+        start.horizontal_offset(relative.character)
+    }
+    else {
+        lsp::Position { line: start.line + relative.line, character: relative.character }
+    }
+}
+
+/// Consume the remainder of a `$(...)`, `${...}`, or backquote expansion.
+fn extract_expansion(chars: &mut PosChars, open: char, close: char) -> Option<()> {
+    let mut depth = 1;
+    while depth != 0 {
+        match chars.next()? {
+            '\\' => _ = chars.next()?,
+            char if char == close => depth -= 1,
+            char if char == open => depth += 1,
+            _ => {}
+        }
+    }
+    Some(())
+}
+
+fn variable_value(document: &db::Document, position: lsp::Position) -> Option<db::Location> {
+    let symbol = document.info.references.iter().find(|r| r.reference.range.contains(position))?;
+    match document.info.symbols[symbol.id].kind {
+        db::SymbolKind::Variable(id) => document.info.variables[id].value,
+        _ => None,
+    }
+}
+
+fn evaluate_expansion(
+    document: &db::Document,
+    location: db::Location,
+    chars: &mut PosChars,
+    directory: &Path,
+    depth: u32,
+) -> Option<PathBuf> {
+    let text = location.view.string(&document.text);
+    let start = chars.offset as usize - 1;
+    let name_position = absolute_position(location.range.start, chars.position);
+
+    let is_name = |char: char| char.is_alphanumeric() || char == '_';
+    if text[start..].starts_with('`') {
+        extract_expansion(chars, '`', '`')?;
+        return is_script_directory(&text[start..chars.offset as usize])
+            .then(|| directory.to_owned());
+    }
+    match chars.next()? {
+        '(' => extract_expansion(chars, '(', ')')?,
+        '{' if chars.peek().is_some_and(is_name) => {
+            let name_position = absolute_position(location.range.start, chars.position);
+            while chars.next_if(is_name).is_some() {}
+            if chars.consume('}') {
+                let value = variable_value(document, name_position)?;
+                return evaluate_at(document, value, directory, depth + 1);
+            }
+            extract_expansion(chars, '{', '}')?
+        }
+        '{' => extract_expansion(chars, '{', '}')?,
+        char if is_name(char) => {
+            while chars.next_if(is_name).is_some() {}
+            let value = variable_value(document, name_position)?;
+            return evaluate_at(document, value, directory, depth + 1);
+        }
+        _ => return None,
+    }
+
+    is_script_directory(&text[start..chars.offset as usize]).then(|| directory.to_owned())
+}
+
+fn evaluate_at(
+    document: &db::Document,
+    location: db::Location,
+    directory: &Path,
+    depth: u32,
+) -> Option<PathBuf> {
+    if depth == MAX_DEPTH {
+        return None;
+    }
+    let text = location.view.string(&document.text);
+    if is_script_directory(text) {
+        return Some(directory.to_owned());
+    }
+
+    let mut chars = PosChars::new(text);
+    let mut string = String::new();
+    let mut base: Option<PathBuf> = None;
+    let mut quoted = false;
+
+    while let Some(char) = chars.next() {
+        let at_start = string.is_empty() && base.is_none();
+        match char {
+            '"' => quoted = !quoted,
+            '\'' if !quoted => loop {
+                match chars.next()? {
+                    '\'' => break,
+                    char => string.push(char),
+                }
+            },
+            '\\' => string.push(chars.next()?),
+            // `~user` names another user's home directory, which is not looked up.
+            '~' if at_start && !quoted && matches!(chars.peek(), None | Some('/')) => {
+                base = Some(home()?);
+            }
+            '~' if at_start && !quoted => return None,
+            '$' | '`' if at_start => {
+                base = Some(evaluate_expansion(document, location, &mut chars, directory, depth)?);
+            }
+            '$' | '`' => return None,
+            '*' | '?' | '[' if !quoted => return None,
+            char => string.push(char),
+        }
+    }
+
+    let path = match base {
+        Some(base) => base.join(string.trim_start_matches('/')),
+        None if string.is_empty() => return None,
+        None => directory.join(string),
+    };
+    Some(path.components().collect()) // Normalizes away `.` components.
+}
+
+/// Statically evaluate a shell word that denotes a file system path.
+/// Relative paths are resolved relative to `directory`.
+pub fn evaluate(
+    document: &db::Document,
+    location: db::Location,
+    directory: &Path,
+) -> Option<PathBuf> {
+    evaluate_at(document, location, directory, 0)
+}
+
+/// Check whether `call` reads another script with `source` or `.`.
+pub fn is_source(info: &db::DocumentInfo, call: &db::Call) -> bool {
+    let symbol = &info.symbols[call.id];
+    let is_function = matches!(symbol.kind, db::SymbolKind::Function(_));
+    !is_function && matches!(symbol.name.as_str(), "source" | ".")
+}
+
+/// Check whether `text` is a literal absolute path, possibly quoted.
+pub fn is_literal_absolute(text: &str) -> bool {
+    text.trim_start_matches(['"', '\'']).starts_with('/') && !text.contains(['$', '`'])
+}
+
+/// Where `path` ends up when `old`, which may be a directory, is renamed to `new`.
+pub fn renamed(path: &Path, old: &Path, new: &Path) -> Option<PathBuf> {
+    let rest = path.strip_prefix(old).ok()?;
+    Some(if rest.as_os_str().is_empty() { new.to_owned() } else { new.join(rest) })
+}
+
+/// Express the absolute `path` relative to the absolute `directory`.
+fn relative_to(path: &Path, directory: &Path) -> PathBuf {
+    let common = (path.components().zip(directory.components()))
+        .take_while(|(a, b)| a == b)
+        .count();
+    let parents = directory.components().skip(common).map(|_| Component::ParentDir);
+    let relative: PathBuf = parents.chain(path.components().skip(common)).collect();
+    if relative.starts_with("..") {
+        relative
+    }
+    else {
+        Path::new(".").join(relative) // Names without a slash would be looked up in `PATH`.
+    }
+}
+
+/// Rewrite the trailing literal part of `text` that differs between `old` and `new`, which keeps
+/// any leading expansions intact.
+fn replace_suffix(text: &str, old: &Path, new: &Path) -> Option<String> {
+    let common = old.components().zip(new.components()).take_while(|(a, b)| a == b).count();
+    let old_tail: PathBuf = old.components().skip(common).collect();
+    let new_tail: PathBuf = new.components().skip(common).collect();
+    let (old_tail, new_tail) = (old_tail.to_str()?, new_tail.to_str()?);
+
+    let quote = text.chars().last().filter(|&char| char == '"' || char == '\'');
+    let body = quote.map_or(text, |quote| &text[..text.len() - quote.len_utf8()]);
+    let prefix = body.strip_suffix(old_tail)?;
+    let at_boundary = prefix.is_empty() || prefix.ends_with(['/', '"', '\'']);
+    let closing = &text[body.len()..];
+    (at_boundary && !old_tail.is_empty() && lex::quote(old_tail, quote) == old_tail)
+        .then(|| format!("{prefix}{}{closing}", lex::quote(new_tail, quote)))
+}
+
+/// Replace a literal path with one that refers to `new`, keeping it absolute or relative.
+fn replace_literal(text: &str, directory: &Path, new: &Path) -> Option<String> {
+    let (None, literal) = lex::unquote(text)?
+    else {
+        return None;
+    };
+    let path = if literal.starts_with('/') { new.to_owned() } else { relative_to(new, directory) };
+    Some(actions::quote_for(path.to_str()?, db::Quoting::Unquoted))
+}
+
+/// Resolve `.` and `..` components lexically.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => _ = normal.pop(),
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+/// Where `path` ends up after the `(old, new)` renames, which happen together. The most specific
+/// rename that applies wins.
+fn renamed_by(path: &Path, renames: &[(&Path, &Path)]) -> Option<PathBuf> {
+    (renames.iter())
+        .filter(|(old, _)| path.starts_with(old))
+        .max_by_key(|(old, _)| old.components().count())
+        .and_then(|(old, new)| renamed(path, old, new))
+}
+
+/// Compute edits that update the scripts that `document`, located at `path`, sources or invokes by
+/// path after the `(old, new)` renames, which happen together. Each word gets at most one edit.
+/// When the renames move the document to another directory, plain relative paths are rewritten
+/// to still reach their targets, but paths built from the script's directory, like
+/// `"$(dirname "$0")/lib.sh"`, are left as they are.
+pub fn rename_edits(
+    document: &db::Document,
+    path: &Path,
+    renames: &[(&Path, &Path)],
+) -> Vec<lsp::TextEdit> {
+    let directory = path.parent().unwrap_or(Path::new("/"));
+    let info = &document.info;
+    let words = info.calls.iter().filter_map(|call| {
+        if is_source(info, call) {
+            call.arguments.first().copied()
+        }
+        else {
+            let is_path = call.name.view.string(&document.text).contains('/');
+            (is_path && matches!(info.symbols[call.id].kind, db::SymbolKind::Command))
+                .then_some(call.name)
+        }
+    });
+    let new_directory = (renamed_by(path, renames).as_deref().and_then(Path::parent))
+        .filter(|new_directory| *new_directory != directory)
+        .map(Path::to_owned);
+    (words.filter_map(|word| {
+        let path = normalize(&evaluate(document, word, directory)?);
+        let target = renamed_by(&path, renames);
+        let text = word.view.string(&document.text);
+        let relative = lex::unquote(text)
+            .filter(|(quote, literal)| quote.is_none() && !literal.starts_with('/'));
+        let new_text = match (&new_directory, relative) {
+            (Some(new_directory), Some((_, literal))) => {
+                let target = target.unwrap_or(path);
+                if normalize(&new_directory.join(literal)) == target {
+                    return None;
+                }
+                replace_literal(text, new_directory, &target)?
+            }
+            (Some(_), None) if !is_literal_absolute(text) => return None,
+            _ => {
+                let target = target?;
+                replace_suffix(text, &path, &target)
+                    .or_else(|| replace_literal(text, directory, &target))?
+            }
+        };
+        Some(lsp::TextEdit { range: word.range, new_text })
+    }))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Settings;
+    use crate::db;
+    use std::path::{Path, PathBuf};
+
+    fn evaluate_arguments(text: &str) -> Vec<Option<PathBuf>> {
+        let mut document = db::Document::new(text);
+        document.info = crate::parse::parse(text, &Settings::default());
+        (document.info.calls.iter())
+            .filter(|call| super::is_source(&document.info, call))
+            .flat_map(|call| call.arguments.iter())
+            .map(|&argument| super::evaluate(&document, argument, Path::new("/dir")))
+            .collect()
+    }
+
+    fn rename_all(text: &str, path: &str, renames: &[(&str, &str)]) -> Vec<String> {
+        let mut document = db::Document::new(text);
+        document.info = crate::parse::parse(text, &Settings::default());
+        let renames: Vec<(&Path, &Path)> =
+            renames.iter().map(|(old, new)| (old.as_ref(), new.as_ref())).collect();
+        let edits = super::rename_edits(&document, path.as_ref(), &renames);
+        edits.into_iter().map(|edit| edit.new_text).collect()
+    }
+
+    fn rename(text: &str, old: &str, new: &str) -> Vec<String> {
+        rename_all(text, "/dir/a.sh", &[(old, new)])
+    }
+
+    #[test]
+    fn rename_edits() {
+        assert_eq!(rename(". ./lib.sh\n", "/dir/lib.sh", "/dir/util.sh"), ["./util.sh"]);
+        assert_eq!(rename(". lib/a.sh\n", "/dir/lib", "/dir/src"), ["src/a.sh"]);
+        assert_eq!(rename("./x.sh \"$1\"\n", "/dir/x.sh", "/dir/a b.sh"), ["./a\\ b.sh"]);
+        assert_eq!(rename(". \"$(dirname \"$0\")/x.sh\"\n", "/dir/x.sh", "/dir/y.sh"), [
+            "\"$(dirname \"$0\")/y.sh\""
+        ]);
+        assert_eq!(rename(". ./x.sh\n", "/dir/x.sh", "/other/x.sh"), ["../other/x.sh"]);
+        assert_eq!(rename(". '/dir/x.sh'\n", "/dir/x.sh", "/y.sh"), ["'/y.sh'"]);
+        assert_eq!(rename("source /dir/x.sh\n", "/dir/x.sh", "/a b/x.sh"), ["/a\\ b/x.sh"]);
+        assert!(rename(". ./y.sh\nx.sh\n", "/dir/x.sh", "/dir/z.sh").is_empty());
+        assert_eq!(rename(". ../x.sh\n", "/x.sh", "/y.sh"), ["../y.sh"]);
+    }
+
+    #[test]
+    fn rename_moved_document() {
+        let rename =
+            |text: &str, old: &str, new: &str| rename_all(text, "/dir/sub/a.sh", &[(old, new)]);
+        assert_eq!(rename(". ./lib.sh\n", "/dir/sub/a.sh", "/dir/a.sh"), ["./sub/lib.sh"]);
+        assert_eq!(rename(". ../lib.sh\n", "/dir/sub/a.sh", "/dir/a.sh"), ["./lib.sh"]);
+        assert!(rename(". ./lib.sh\n", "/dir/sub", "/dir/src").is_empty());
+        assert!(rename(". /lib.sh\n", "/dir/sub", "/dir/src").is_empty());
+        assert!(rename(". \"$(dirname \"$0\")/lib.sh\"\n", "/dir/sub", "/src").is_empty());
+    }
+
+    #[test]
+    fn rename_together() {
+        let moves = [("/w/d1/a.sh", "/w/d2/a.sh"), ("/w/d1/lib.sh", "/w/d2/lib.sh")];
+        assert!(rename_all(". ./lib.sh\n", "/w/d1/a.sh", &moves).is_empty());
+        assert_eq!(rename_all(". ./d1/lib.sh\n", "/w/b.sh", &moves), ["./d2/lib.sh"]);
+        let moves = [("/w/d1", "/w/d3"), ("/w/d1/lib.sh", "/w/lib.sh")];
+        assert_eq!(rename_all(". ./lib.sh\n", "/w/d1/a.sh", &moves), ["../lib.sh"]);
+    }
+
+    #[test]
+    fn script_directory() {
+        assert!(super::is_script_directory("$(dirname \"$0\")"));
+        assert!(super::is_script_directory("\"$(dirname -- \"$0\")\""));
+        assert!(super::is_script_directory("$(cd \"$(dirname \"${BASH_SOURCE[0]}\")\" && pwd)"));
+        assert!(super::is_script_directory("${0%/*}"));
+        assert!(!super::is_script_directory("$(dirname \"$1\")"));
+        assert!(!super::is_script_directory("$0"));
+    }
+
+    #[test]
+    fn evaluate() {
+        let path = |str: &str| Some(PathBuf::from(str));
+        assert_eq!(evaluate_arguments(". ./lib.sh\n"), [path("/dir/lib.sh")]);
+        assert_eq!(evaluate_arguments("source '/a b'/c\n"), [path("/a b/c")]);
+        assert_eq!(evaluate_arguments(". \"$(dirname \"$0\")/x.sh\"\n"), [path("/dir/x.sh")]);
+        assert_eq!(evaluate_arguments("D=$(dirname \"$0\")\n. \"$D/x.sh\"\n"), [path("/dir/x.sh")]);
+        assert_eq!(evaluate_arguments("D=${0%/*}/lib\n. \"${D}/x.sh\"\n"), [path("/dir/lib/x.sh")]);
+        assert_eq!(evaluate_arguments(". \"$1/x.sh\"\n"), [None]);
+        assert_eq!(evaluate_arguments(". ./*.sh\n"), [None]);
+        let home = super::home().map(|home| home.join("lib.sh"));
+        assert_eq!(evaluate_arguments(". ~/lib.sh\n"), [home]);
+        assert_eq!(evaluate_arguments(". ~bob/lib.sh\n"), [None]);
+        assert_eq!(evaluate_arguments(". ./~/lib.sh\n"), [path("/dir/~/lib.sh")]);
+    }
+}
@@ -26,6 +26,10 @@ impl<'a> PosChars<'a> {
     pub fn new(input: &'a str) -> PosChars<'a> {
         PosChars { position: Position::default(), offset: 0, chars: input.chars(), next: None }
     }
+    /// Continue from `offset` in `input`, which corresponds to `position`.
+    pub fn resume(input: &'a str, position: Position, offset: u32) -> PosChars<'a> {
+        PosChars { position, offset, chars: input[offset as usize..].chars(), next: None }
+    }
     pub fn peek(&mut self) -> Option<char> {
         if self.next.is_none() {
             self.next = self.chars.next()
@@ -1,4 +1,4 @@
-use std::io::{Read, Write};
+use std::io::{BufRead, Read, Write};
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct JsonRpc;
@@ -79,8 +79,8 @@ impl serde::Serialize for JsonRpc {
     }
 }
 
-fn consume(input: &mut dyn Read, bytes: usize) -> bool {
-    input.bytes().take(bytes).count() == bytes
+fn consume(input: &mut impl BufRead, bytes: usize) -> bool {
+    input.by_ref().bytes().take(bytes).count() == bytes
 }
 
 pub fn write_message(output: &mut dyn Write, content: &str) -> std::io::Result<()> {
@@ -88,7 +88,7 @@ pub fn write_message(output: &mut dyn Write, content: &str) -> std::io::Result<(
     output.flush()
 }
 
-pub fn read_message(input: &mut dyn Read) -> std::io::Result<String> {
+pub fn read_message(input: &mut impl BufRead) -> std::io::Result<String> {
     let error = |msg| Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
 
     if !consume(input, "Content-Length: ".len()) {
@@ -96,7 +96,7 @@ pub fn read_message(input: &mut dyn Read) -> std::io::Result<String> {
     }
 
     let mut length: usize = 0;
-    for byte in input.bytes() {
+    for byte in input.by_ref().bytes() {
         let byte = byte?;
         if byte.is_ascii_digit() {
             length *= 10;
@@ -159,6 +159,9 @@ impl Error {
 }
 
 impl Request {
+    pub fn new(id: u32, method: impl Into<String>, params: serde_json::Value) -> Self {
+        Self { params, method: method.into(), id: Some(id), jsonrpc: JsonRpc }
+    }
     pub fn notification(method: impl Into<String>, params: serde_json::Value) -> Self {
         Self { params, method: method.into(), id: None, jsonrpc: JsonRpc }
     }
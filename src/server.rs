@@ -1,20 +1,59 @@
 use crate::config::{self, Cmdline, Settings};
+use crate::external::options::CommandOption;
+use crate::external::shellcheck;
+use crate::indexvec::VecIndex;
 use crate::shell::Shell;
-use crate::{db, env, external, lsp, parse, rpc};
+use crate::{actions, complete, db, env, external, lex, lsp, parse, paths, rpc, shell, syntax};
 use serde_json::{Value as Json, from_value, json};
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::rc::Rc;
+
+/// Apply every Shellcheck fix in a document. Arguments: document URI.
+const COMMAND_APPLY_FIXES: &str = "shell.applyShellcheckFixes";
+/// Disable a Shellcheck code for a whole document. Arguments: document URI, code.
+const COMMAND_DISABLE_IN_FILE: &str = "shell.disableShellcheckInFile";
+/// Show the shell dialect of a document and the status of the integrations. Arguments: optional
+/// document URI.
+const COMMAND_SHOW_STATUS: &str = "shell.showStatus";
+/// Analyze open documents again, so that changes to executables in `PATH` are picked up.
+const COMMAND_RESCAN_EXECUTABLES: &str = "shell.rescanExecutables";
+
+const COMMANDS: &[&str] = &[
+    COMMAND_APPLY_FIXES,
+    COMMAND_DISABLE_IN_FILE,
+    COMMAND_SHOW_STATUS,
+    COMMAND_RESCAN_EXECUTABLES,
+];
 
 struct Server<'io> {
     db: db::Database,
     settings: Settings,
+    client: lsp::ClientCapabilities,
+    workspace_folders: Vec<PathBuf>,
+    command_options: HashMap<String, Rc<[CommandOption]>>,
+    unopened_reports: HashMap<PathBuf, UnopenedReport>,
+    /// Incremented whenever the settings or the executables found through `PATH` change, so that
+    /// diagnostic result identifiers of unopened documents change along with their reports.
+    generation: u32,
     initialized: bool,
     exit_code: Option<ExitCode>,
-    input: &'io mut dyn std::io::Read,
+    /// The identifier of the last request sent to the client.
+    request_id: u32,
+    input: &'io mut dyn std::io::BufRead,
     output: &'io mut dyn std::io::Write,
 }
 
+/// The diagnostics of a document that has not been opened, computed when its file had the given
+/// modification time.
+struct UnopenedReport {
+    modified: std::time::SystemTime,
+    result_id: String,
+    diagnostics: Json,
+}
+
 fn server_capabilities(settings: &Settings) -> Json {
     json!({
         "textDocumentSync": {
@@ -23,25 +62,45 @@ fn server_capabilities(settings: &Settings) -> Json {
         },
         "semanticTokensProvider": {
             "legend": {
-                "tokenTypes": ["keyword", "parameter", "string"],
-                "tokenModifiers": ["documentation"],
+                "tokenTypes": lsp::SEMANTIC_TOKEN_KINDS,
+                "tokenModifiers": lsp::SEMANTIC_TOKEN_MODIFIERS,
             },
-            "full": true,
+            "full": { "delta": true },
+            "range": true,
         },
         "hoverProvider": true,
         "definitionProvider": true,
         "referencesProvider": true,
         "documentSymbolProvider": true,
         "documentHighlightProvider": true,
+        "documentLinkProvider": { "resolveProvider": false },
+        "diagnosticProvider": { "interFileDependencies": false, "workspaceDiagnostics": true },
         "documentFormattingProvider": settings.integrate.shfmt.enable,
         "documentRangeFormattingProvider": settings.integrate.shfmt.enable,
         "codeActionProvider": true,
+        "codeLensProvider": { "resolveProvider": false },
         "inlayHintProvider": { "resolveProvider": false },
         "renameProvider": { "prepareProvider": true },
-        "completionProvider": { "triggerCharacters": ["$", "{"] },
+        "executeCommandProvider": { "commands": COMMANDS },
+        "completionProvider": { "triggerCharacters": ["$", "{", "/"], "resolveProvider": true },
+        "workspace": {
+            "fileOperations": {
+                "willRename": { "filters": rename_filters() },
+                "didRename": { "filters": rename_filters() },
+            },
+        },
     })
 }
 
+/// Renames of shell scripts and of directories, which may contain scripts.
+fn rename_filters() -> Json {
+    let scripts = format!("**/*.{{{}}}", env::SCRIPT_EXTENSIONS.join(","));
+    json!([
+        { "pattern": { "glob": scripts, "matches": "file" } },
+        { "pattern": { "glob": "**", "matches": "folder" } },
+    ])
+}
+
 fn document_id(
     db: &db::Database,
     id: &lsp::DocumentIdentifier,
@@ -92,6 +151,93 @@ fn collect_references<T>(
     find_references(&document.info, position).map(projection).collect()
 }
 
+/// Find the symbol at `position`, which must be a function or a variable defined by the document.
+fn rename_target(
+    info: &db::DocumentInfo,
+    position: lsp::Position,
+) -> Result<Option<db::SymbolReference>, rpc::Error> {
+    let Some(reference) = find_symbol(info, position) else { return Ok(None) };
+    let reason = match info.symbols[reference.id].kind {
+        db::SymbolKind::Function(_) => return Ok(Some(reference)),
+        db::SymbolKind::Variable(id) => match info.variables[id].kind {
+            db::VariableKind::Environment => "Environment variables can not be renamed",
+            db::VariableKind::Global | db::VariableKind::Local => return Ok(Some(reference)),
+        },
+        db::SymbolKind::Parameter(_) => "Positional parameters can not be renamed",
+        db::SymbolKind::Special(_) => "Special parameters can not be renamed",
+        db::SymbolKind::Command => "External commands can not be renamed",
+        db::SymbolKind::Builtin => "Builtins can not be renamed",
+        db::SymbolKind::Error => "This symbol can not be renamed",
+    };
+    Err(rpc::Error::request_failed(reason))
+}
+
+/// The range of the function definition that declares the local variable `id`.
+fn local_scope(info: &db::DocumentInfo, id: db::VariableId) -> Option<lsp::Range> {
+    let variable = &info.variables[id];
+    if !matches!(variable.kind, db::VariableKind::Local) {
+        return None;
+    }
+    let position = variable.first_assignment?.range.start;
+    (info.functions.underlying.iter())
+        .filter_map(|function| function.definition.map(|definition| definition.range))
+        .filter(|definition| definition.contains(position))
+        .min_by_key(|definition| std::cmp::Reverse(definition.start))
+}
+
+/// Make sure that `name` is valid for the symbol `target`, and that it does not refer to another
+/// symbol where `target` is referenced.
+fn check_new_name(
+    info: &db::DocumentInfo,
+    target: db::SymbolId,
+    name: &str,
+) -> Result<(), rpc::Error> {
+    let symbol = &info.symbols[target];
+    if shell::is_reserved_word(name, info.shell) {
+        return Err(rpc::Error::request_failed(format!("'{name}' is a reserved word")));
+    }
+    let is_function = matches!(symbol.kind, db::SymbolKind::Function(_));
+    let (is_valid, description) = if is_function {
+        (parse::is_identifier(name, info.shell), "function name")
+    }
+    else {
+        (lex::is_name(name), "variable name")
+    };
+    if !is_valid {
+        return Err(rpc::Error::request_failed(format!("'{name}' is not a valid {description}")));
+    }
+
+    let scope = match symbol.kind {
+        db::SymbolKind::Variable(id) => local_scope(info, id),
+        _ => None,
+    };
+    let conflict = (info.symbols.underlying.iter()).enumerate().find(|&(index, other)| {
+        let same_namespace = match other.kind {
+            db::SymbolKind::Variable(_) => !is_function,
+            db::SymbolKind::Function(_) | db::SymbolKind::Command | db::SymbolKind::Builtin => {
+                is_function
+            }
+            _ => false,
+        };
+        let in_scope = scope.is_none_or(|scope| {
+            (other.ref_indices.iter())
+                .any(|&index| scope.contains_range(info.references[index as usize].reference.range))
+        });
+        index != target.get() && other.name == name && same_namespace && in_scope
+    });
+    match conflict.map(|(_, other)| other.kind) {
+        Some(db::SymbolKind::Variable(id)) => {
+            let description = info.variables[id].kind.describe();
+            Err(rpc::Error::request_failed(format!("{description} '{name}' already exists")))
+        }
+        Some(db::SymbolKind::Function(_)) => {
+            Err(rpc::Error::request_failed(format!("Function '{name}' already exists")))
+        }
+        Some(_) => Err(rpc::Error::request_failed(format!("Command '{name}' already exists"))),
+        None => Ok(()),
+    }
+}
+
 fn is_path(name: &str) -> bool {
     name.contains(std::path::MAIN_SEPARATOR)
 }
@@ -133,55 +279,6 @@ fn get_line(text: &str, line: u32) -> Result<&str, rpc::Error> {
     text.lines().nth(line as usize).ok_or_else(error)
 }
 
-fn is_word(char: char) -> bool {
-    char.is_alphanumeric() || "_-".contains(char)
-}
-
-fn determine_completion_kind(
-    prefix: &str,
-    cursor: lsp::Position,
-) -> (usize, lsp::CompletionItemKind) {
-    for (index, char) in prefix.chars().rev().enumerate() {
-        let offset = cursor.character as usize - index;
-        if "${".contains(char) {
-            return (offset, lsp::CompletionItemKind::Variable);
-        }
-        else if !is_word(char) {
-            return (offset, lsp::CompletionItemKind::Function);
-        }
-    }
-    (0, lsp::CompletionItemKind::Function)
-}
-
-fn completion(range: lsp::Range, name: &str, kind: lsp::CompletionItemKind) -> Json {
-    json!({
-        "label": name,
-        "kind": kind,
-        "textEdit": { "range": range, "newText": name },
-    })
-}
-
-fn variable_completions(document: &db::Document, range: lsp::Range, prefix: &str) -> Json {
-    (document.info.symbols.underlying.iter())
-        .filter(|symbol| {
-            matches!(symbol.kind, db::SymbolKind::Variable(_)) && symbol.name.starts_with(prefix)
-        })
-        .map(|symbol| completion(range, &symbol.name, lsp::CompletionItemKind::Variable))
-        .collect()
-}
-
-fn function_completions(document: &db::Document, range: lsp::Range, prefix: &str) -> Json {
-    (document.info.symbols.underlying.iter())
-        .filter(|symbol| {
-            matches!(
-                symbol.kind,
-                db::SymbolKind::Command | db::SymbolKind::Builtin | db::SymbolKind::Function(_)
-            ) && symbol.name.starts_with(prefix)
-        })
-        .map(|symbol| completion(range, &symbol.name, lsp::CompletionItemKind::Function))
-        .collect()
-}
-
 fn find_executable(name: &str, settings: &Settings) -> Option<PathBuf> {
     (settings.environment.path.as_deref().map(Cow::Borrowed))
         .or_else(|| env::path_directories().map(Cow::Owned))
@@ -202,12 +299,27 @@ fn help(shell: Shell, name: &str, settings: &Settings) -> Option<String> {
     if settings.integrate.help.enable { external::help::documentation(shell, name) } else { None }
 }
 
-fn describe_variable(kind: db::VariableKind) -> &'static str {
-    match kind {
-        db::VariableKind::Global => "Variable",
-        db::VariableKind::Local => "Local variable",
-        db::VariableKind::Environment => "Environment variable",
+fn command_options(shell: Shell, name: &str, settings: &Settings) -> Vec<CommandOption> {
+    if crate::shell::builtins(shell).contains(&name) {
+        return help(shell, name, settings).map_or_else(Vec::new, |help| {
+            external::options::parse(&help)
+        });
+    }
+    if let Some(manual) = manual(shell, name, settings) {
+        let options = external::options::parse(&manual);
+        if !options.is_empty() {
+            return options;
+        }
     }
+    // Only commands found through `PATH` are run, never scripts referred to by path.
+    if settings.integrate.help.command_help && !is_path(name) {
+        if let Some(help) = find_executable(name, settings)
+            .and_then(|path| external::help::command_help(path.to_str()?))
+        {
+            return external::options::parse(&help);
+        }
+    }
+    Vec::new()
 }
 
 fn special_markdown(special: db::Special) -> String {
@@ -237,7 +349,7 @@ fn symbol_markup(
     match symbol.kind {
         db::SymbolKind::Variable(id) => {
             let variable = &document.info.variables[id];
-            let mut markdown = format!("# {} `{}`", describe_variable(variable.kind), symbol.name);
+            let mut markdown = format!("# {} `{}`", variable.kind.describe(), symbol.name);
             if let Some(desc) = &variable.description {
                 write!(markdown, "\n---\n{desc}")?;
             }
@@ -318,15 +430,70 @@ fn symbol_markup(
     }
 }
 
+/// Like `symbol_markup`, but only includes the synopsis of manual pages.
+fn completion_markup(
+    document: &db::Document,
+    symbol: &db::Symbol,
+    settings: &Settings,
+) -> Result<lsp::MarkupContent, rpc::Error> {
+    use std::fmt::Write;
+    if !matches!(symbol.kind, db::SymbolKind::Command) {
+        return symbol_markup(document, symbol, settings);
+    }
+    let mut markdown = format!("# Command `{}`", symbol.name);
+    if let Some(synopsis) = manual(document.info.shell, &symbol.name, settings)
+        .and_then(|manual| external::man::synopsis(&manual))
+    {
+        write!(markdown, "\n---\n```man\n{synopsis}\n```")?;
+    }
+    Ok(lsp::MarkupContent::markdown(markdown))
+}
+
 fn symbol_hover(
     document: &db::Document,
     symbol: db::SymbolReference,
+    explanations: Option<String>,
     settings: &Settings,
 ) -> Result<Json, rpc::Error> {
-    Ok(json!({
-        "contents": symbol_markup(document, &document.info.symbols[symbol.id], settings)?,
-        "range": symbol.reference.range,
-    }))
+    let mut markup = symbol_markup(document, &document.info.symbols[symbol.id], settings)?;
+    if let Some(explanations) = explanations {
+        markup = lsp::MarkupContent::markdown(format!("{}\n\n---\n\n{explanations}", markup.value));
+    }
+    Ok(json!({ "contents": markup, "range": symbol.reference.range }))
+}
+
+/// Explain the Shellcheck code under the cursor in a directive comment, or the codes of the
+/// diagnostics at `position`.
+fn shellcheck_hover(
+    document: &db::Document,
+    position: lsp::Position,
+) -> Option<(String, lsp::Range)> {
+    let line = get_line(&document.text, position.line).ok()?;
+    if let Some((code, columns)) = shellcheck::directive_code_at(line, position.character) {
+        let start = lsp::Position { line: position.line, character: columns.start };
+        let range = lsp::Range { start, end: start.horizontal_offset(columns.len() as u32) };
+        return Some((shellcheck::explanation_markdown(code), range));
+    }
+    let diagnostics: Vec<&lsp::Diagnostic> = (document.info.diagnostics.iter())
+        .filter(|diagnostic| diagnostic.code != 0 && diagnostic.range.contains(position))
+        .collect();
+    let explanations: Vec<String> = (diagnostics.iter())
+        .map(|diagnostic| shellcheck::explanation_markdown(diagnostic.code))
+        .collect();
+    Some((explanations.join("\n\n---\n\n"), diagnostics.first()?.range))
+}
+
+/// Document the reserved word or operator at `position`. If a command symbol is also there, a
+/// reserved word that the shell does not support, like `time` in a POSIX shell, is left to it.
+fn syntax_hover(
+    document: &db::Document,
+    position: lsp::Position,
+    is_symbol: bool,
+) -> Option<(String, lsp::Range)> {
+    let shell = document.info.shell;
+    let (syntax, range) = syntax::find_at(&document.text, &document.info.heredocs, position)
+        .filter(|(syntax, _)| !is_symbol || syntax.shells.contains(&shell))?;
+    Some((syntax.markdown(shell), range))
 }
 
 fn push_diagnostics(
@@ -341,21 +508,174 @@ fn push_diagnostics(
     rpc::write_message(output, &serde_json::to_string(&notification)?)
 }
 
-fn analyze(document: &mut db::Document, settings: &Settings) {
+fn analyze(document: &mut db::Document, revision: u32, settings: &Settings) {
+    document.revision = revision;
     document.info = parse::parse(&document.text, settings);
+    let mut shellcheck_succeeded = false;
     if settings.integrate.shellcheck.enable {
-        match external::shellcheck::analyze(
-            &document.text,
-            document.info.shell,
-            &settings.integrate.shellcheck,
-        ) {
-            Ok(external::shellcheck::Info { diagnostics, actions }) => {
+        // Shellcheck reports the codes disabled by directives, so that unused directives can be
+        // found. The suppressed diagnostics are removed afterwards.
+        let suppressions = &document.info.suppressions;
+        let text = if suppressions.is_empty() {
+            Cow::Borrowed(document.text.as_str())
+        }
+        else {
+            Cow::Owned(shellcheck::without_suppressions(&document.text, suppressions))
+        };
+        match shellcheck::analyze(&text, document.info.shell, &settings.integrate.shellcheck) {
+            Ok(Some(shellcheck::Info { diagnostics, actions })) => {
+                // Shellcheck checks everything that is checked natively, with its own fixes. It
+                // deliberately skips some cases, like expansions of variables known to be numbers.
+                let is_native = |code: &i32| parse::NATIVE_CODES.contains(code);
+                document.info.diagnostics.retain(|diagnostic| !is_native(&diagnostic.code));
+                document.info.actions.retain(|action| match &action.kind {
+                    db::ActionKind::Fix { code, .. } => !is_native(code),
+                    _ => true,
+                });
                 document.info.diagnostics.extend(diagnostics);
                 document.info.actions.extend(actions);
+                shellcheck_succeeded = true;
             }
+            Ok(None) => {}
             Err(error) => eprintln!("[debug] Shellcheck failed: {error}"),
         }
     }
+    check_suppressions(&mut document.info, shellcheck_succeeded);
+}
+
+/// Hide diagnostics that are disabled by Shellcheck directives, and report disabled codes that are
+/// not reported where their directive applies. When Shellcheck is unavailable, only the codes that
+/// are checked natively are considered.
+fn check_suppressions(info: &mut db::DocumentInfo, use_shellcheck: bool) {
+    if info.suppressions.is_empty() {
+        return;
+    }
+    let is_checked = |code| use_shellcheck || parse::NATIVE_CODES.contains(&code);
+    let unused = shellcheck::unused_suppressions(&info.suppressions, &info.diagnostics, is_checked);
+    let is_suppressed =
+        |diagnostic: &lsp::Diagnostic| shellcheck::is_suppressed(&info.suppressions, diagnostic);
+    let suppressed: Vec<lsp::Range> = (info.diagnostics.iter())
+        .filter(|diagnostic| is_suppressed(diagnostic))
+        .map(|diagnostic| diagnostic.range)
+        .collect();
+    info.diagnostics.retain(|diagnostic| !is_suppressed(diagnostic));
+    info.actions.retain(|action| !suppressed.contains(&action.range));
+    info.diagnostics.extend(unused);
+}
+
+fn diagnostic_report(
+    result_id: String,
+    previous_result_id: Option<&str>,
+    items: impl FnOnce() -> Json,
+) -> Json {
+    if previous_result_id == Some(result_id.as_str()) {
+        json!({ "kind": "unchanged", "resultId": result_id })
+    }
+    else {
+        json!({ "kind": "full", "resultId": result_id, "items": items() })
+    }
+}
+
+fn open_document_report(document: &db::Document, previous_result_id: Option<&str>) -> Json {
+    diagnostic_report(document.revision.to_string(), previous_result_id, || {
+        json!(document.info.diagnostics)
+    })
+}
+
+/// Report diagnostics for a document that has not been opened, identified by a hash of its text and
+/// the settings `generation`. Reports are cached until the file is modified. Shellcheck is only
+/// run if enabled for unopened documents.
+fn unopened_document_report(
+    path: &Path,
+    previous_result_id: Option<&str>,
+    settings: &Settings,
+    generation: u32,
+    cache: &mut HashMap<PathBuf, UnopenedReport>,
+) -> std::io::Result<Json> {
+    use std::hash::{Hash, Hasher};
+    let modified = std::fs::metadata(path)?.modified()?;
+    if cache.get(path).is_none_or(|report| report.modified != modified) {
+        let text = std::fs::read_to_string(path)?;
+        let mut hasher = std::hash::DefaultHasher::new();
+        (&text, generation).hash(&mut hasher);
+        let result_id = format!("{:x}", hasher.finish());
+        let mut document = db::Document::new(text);
+        if settings.integrate.shellcheck.unopened_documents {
+            analyze(&mut document, 0, settings);
+        }
+        else {
+            document.info = parse::parse(&document.text, settings);
+            check_suppressions(&mut document.info, false);
+        }
+        let diagnostics = json!(document.info.diagnostics);
+        cache.insert(path.to_owned(), UnopenedReport { modified, result_id, diagnostics });
+    }
+    let report = &cache[path];
+    Ok(diagnostic_report(report.result_id.clone(), previous_result_id, || {
+        report.diagnostics.clone()
+    }))
+}
+
+fn workspace_diagnostics(server: &mut Server, params: lsp::WorkspaceDiagnosticParams) -> Json {
+    let previous: HashMap<PathBuf, String> =
+        params.previous_result_ids.into_iter().map(|id| (id.uri.path, id.value)).collect();
+    let previous = |path: &Path| previous.get(path).map(String::as_str);
+    let item = |path: &Path, mut report: Json| {
+        report["uri"] = json!(lsp::DocumentURI { path: path.to_owned() });
+        report["version"] = Json::Null;
+        report
+    };
+
+    let mut items: Vec<Json> = (server.db.document_paths.iter())
+        .map(|(path, &id)| {
+            item(path, open_document_report(&server.db.documents[id], previous(path)))
+        })
+        .collect();
+
+    for path in server.workspace_folders.iter().flat_map(|folder| env::workspace_scripts(folder)) {
+        if !server.db.document_paths.contains_key(&path) {
+            let (settings, cache) = (&server.settings, &mut server.unopened_reports);
+            let previous = previous(&path);
+            match unopened_document_report(&path, previous, settings, server.generation, cache) {
+                Ok(report) => items.push(item(&path, report)),
+                Err(error) => eprintln!("[debug] Could not read '{}': {error}", path.display()),
+            }
+        }
+    }
+
+    json!({ "items": items })
+}
+
+/// Update the paths that refer to renamed files in open documents and workspace scripts.
+fn rename_files(server: &Server, renames: &[lsp::FileRename]) -> Json {
+    let renames: Vec<(&Path, &Path)> = (renames.iter())
+        .map(|rename| (rename.old_uri.path.as_path(), rename.new_uri.path.as_path()))
+        .collect();
+    let edits =
+        |document: &db::Document, path: &Path| paths::rename_edits(document, path, &renames);
+
+    let mut changes = serde_json::Map::new();
+    let mut insert = |path: &Path, edits: Vec<lsp::TextEdit>| {
+        if !edits.is_empty() {
+            changes.insert(lsp::DocumentURI { path: path.to_owned() }.to_string(), json!(edits));
+        }
+    };
+    for (path, &id) in &server.db.document_paths {
+        insert(path, edits(&server.db.documents[id], path));
+    }
+    for path in server.workspace_folders.iter().flat_map(|folder| env::workspace_scripts(folder)) {
+        if !server.db.document_paths.contains_key(&path) {
+            match std::fs::read_to_string(&path) {
+                Ok(text) => {
+                    let mut document = db::Document::new(text);
+                    document.info = parse::parse(&document.text, &server.settings);
+                    insert(&path, edits(&document, &path));
+                }
+                Err(error) => eprintln!("[debug] Could not read '{}': {error}", path.display()),
+            }
+        }
+    }
+    if changes.is_empty() { Json::Null } else { json!({ "changes": changes }) }
 }
 
 fn whitespace_prefix(str: &str) -> &str {
@@ -371,7 +691,7 @@ fn insert_shellcheck_disable(text: &str, line: u32, code: i32) -> lsp::TextEdit
 
 fn code_action(text: &str, uri: &lsp::DocumentURI, action: &db::Action) -> Json {
     match &action.kind {
-        db::ActionKind::Edit { title, edits } => json!({
+        db::ActionKind::Edit { title, edits } | db::ActionKind::Fix { title, edits, .. } => json!({
             "title": title,
             "edit": { "changes": { uri.to_string(): edits } },
         }),
@@ -385,12 +705,172 @@ fn code_action(text: &str, uri: &lsp::DocumentURI, action: &db::Action) -> Json
     }
 }
 
+fn command_action(title: &str, command: &str, arguments: Json) -> Json {
+    json!({
+        "title": title,
+        "command": { "title": title, "command": command, "arguments": arguments },
+    })
+}
+
+/// Actions that run commands for the Shellcheck diagnostics and fixes among `actions`.
+fn command_actions(uri: &lsp::DocumentURI, actions: &[&db::Action]) -> Vec<Json> {
+    let has_fix = actions.iter().any(|action| matches!(action.kind, db::ActionKind::Fix { .. }));
+    let apply = has_fix.then(|| {
+        command_action("Apply all Shellcheck fixes in this file", COMMAND_APPLY_FIXES, json!([uri]))
+    });
+    let disable = actions.iter().filter_map(|action| match action.kind {
+        db::ActionKind::DisableShellcheck { code } => Some(command_action(
+            &format!("SC{code}: Disable this diagnostic in the whole file"),
+            COMMAND_DISABLE_IN_FILE,
+            json!([uri, code]),
+        )),
+        _ => None,
+    });
+    apply.into_iter().chain(disable).collect()
+}
+
+/// Collect the edits of every Shellcheck fix, skipping fixes that overlap previous ones.
+fn shellcheck_fix_edits(info: &db::DocumentInfo) -> Vec<lsp::TextEdit> {
+    let mut fixes: Vec<&[lsp::TextEdit]> = (info.actions.iter())
+        .filter_map(|action| match &action.kind {
+            db::ActionKind::Fix { edits, .. } => Some(edits.as_slice()),
+            _ => None,
+        })
+        .collect();
+    fixes.sort_by_key(|edits| edits.first().map(|edit| edit.range.start));
+    let overlap = |a: lsp::Range, b: lsp::Range| a.start < b.end && b.start < a.end;
+    let mut result: Vec<lsp::TextEdit> = Vec::new();
+    for edits in fixes {
+        if !edits.iter().any(|edit| result.iter().any(|other| overlap(edit.range, other.range))) {
+            result.extend_from_slice(edits);
+        }
+    }
+    result
+}
+
+/// Insert a directive that disables `code` for the whole document after the shebang.
+fn insert_file_shellcheck_disable(text: &str, code: i32) -> lsp::TextEdit {
+    let line = text.starts_with("#!") as u32;
+    let position = lsp::Position { line, character: 0 };
+    let new_text = format!("# shellcheck disable={code}\n");
+    lsp::TextEdit { range: lsp::Range { start: position, end: position }, new_text }
+}
+
+fn integration_status(name: &str, executable: &str, enabled: bool) -> String {
+    let status = match (enabled, external::exists(executable)) {
+        (true, true) => "enabled",
+        (true, false) => "enabled (not found)",
+        (false, true) => "disabled",
+        (false, false) => "not found",
+    };
+    format!("{name}: {status}")
+}
+
+fn send_request(server: &mut Server, method: &str, params: Json) -> std::io::Result<()> {
+    server.request_id += 1;
+    let request = rpc::Request::new(server.request_id, method, params);
+    rpc::write_message(server.output, &serde_json::to_string(&request)?)
+}
+
+fn show_message(output: &mut dyn std::io::Write, message: &str) -> std::io::Result<()> {
+    let notification = rpc::Request::notification(
+        "window/showMessage",
+        json!({ "type": 3, "message": message }), // Info
+    );
+    rpc::write_message(output, &serde_json::to_string(&notification)?)
+}
+
+fn execute_command(
+    server: &mut Server,
+    params: lsp::ExecuteCommandParams,
+) -> Result<Json, rpc::Error> {
+    let argument = |index: usize| {
+        params.arguments.get(index).cloned().ok_or_else(|| {
+            rpc::Error::invalid_params(format!("Missing argument for {}", params.command))
+        })
+    };
+    match params.command.as_str() {
+        COMMAND_APPLY_FIXES => {
+            let document = lsp::DocumentIdentifier { uri: from_value(argument(0)?)? };
+            let edits = shellcheck_fix_edits(&get_document(&server.db, &document)?.info);
+            if edits.is_empty() {
+                return Err(rpc::Error::request_failed("There are no Shellcheck fixes to apply"));
+            }
+            send_request(server, "workspace/applyEdit", json!({
+                "label": "Apply all Shellcheck fixes",
+                "edit": { "changes": { document.uri.to_string(): edits } },
+            }))?;
+            Ok(Json::Null)
+        }
+        COMMAND_DISABLE_IN_FILE => {
+            let document = lsp::DocumentIdentifier { uri: from_value(argument(0)?)? };
+            let code: i32 = from_value(argument(1)?)?;
+            let text = &get_document(&server.db, &document)?.text;
+            let edit = insert_file_shellcheck_disable(text, code);
+            send_request(server, "workspace/applyEdit", json!({
+                "label": format!("Disable SC{code}"),
+                "edit": { "changes": { document.uri.to_string(): [edit] } },
+            }))?;
+            Ok(Json::Null)
+        }
+        COMMAND_SHOW_STATUS => {
+            let mut lines = Vec::new();
+            let mut shell = Shell::Posix;
+            if let Some(uri) = params.arguments.first() {
+                let document = lsp::DocumentIdentifier { uri: from_value(uri.clone())? };
+                shell = get_document(&server.db, &document)?.info.shell;
+                lines.push(format!("Shell dialect: {}", shell.name()));
+            }
+            let integrate = &server.settings.integrate;
+            let status = |name, enabled| integration_status(name, name, enabled);
+            lines.push(status("shellcheck", integrate.shellcheck.enable));
+            lines.push(status("shfmt", integrate.shfmt.enable));
+            lines.push(status("man", integrate.man.enable));
+            let help = external::help::executable(shell);
+            lines.push(integration_status("help", help, integrate.help.enable));
+            let message = lines.join("\n");
+            show_message(server.output, &message)?;
+            Ok(json!(message))
+        }
+        COMMAND_RESCAN_EXECUTABLES => {
+            refresh(server)?;
+            Ok(Json::Null)
+        }
+        command => Err(rpc::Error::invalid_params(format!("Unknown command: {command}"))),
+    }
+}
+
+/// Forget cached options and reports, and analyze every open document again, after the settings or
+/// the executables found through `PATH` change. Clients that pull diagnostics are asked to pull
+/// them again.
+fn refresh(server: &mut Server) -> std::io::Result<()> {
+    server.command_options.clear();
+    server.unopened_reports.clear();
+    server.generation += 1;
+    let documents: Vec<(PathBuf, db::DocumentId)> =
+        (server.db.document_paths.iter()).map(|(path, &id)| (path.clone(), id)).collect();
+    for (path, id) in documents {
+        let revision = server.db.next_revision();
+        let document = &mut server.db.documents[id];
+        analyze(document, revision, &server.settings);
+        if server.client.text_document.diagnostic.is_none() {
+            let uri = lsp::DocumentURI { path };
+            push_diagnostics(server.output, &uri, &document.info)?;
+        }
+    }
+    if server.client.workspace.diagnostics.refresh_support {
+        send_request(server, "workspace/diagnostic/refresh", Json::Null)?;
+    }
+    Ok(())
+}
+
 fn action_insert_path(
     params: &lsp::DocumentIdentifierRangeParams,
     document: &db::Document,
     settings: &Settings,
 ) -> Option<Json> {
-    let db::SymbolReference { reference, id } = find_symbol(&document.info, params.range.start)?;
+    let db::SymbolReference { reference, id, .. } =
+        find_symbol(&document.info, params.range.start)?;
     let symbol = &document.info.symbols[id];
     if reference.kind == lsp::ReferenceKind::Write
         || matches!(symbol.kind, db::SymbolKind::Variable(_))
@@ -430,6 +910,56 @@ fn document_symbols(info: &db::DocumentInfo) -> Json {
     json!(symbols)
 }
 
+fn document_link(
+    document: &db::Document,
+    argument: db::Location,
+    directory: &Path,
+) -> Option<Json> {
+    let path = paths::evaluate(document, argument, directory)?;
+    path.is_file().then(|| {
+        json!({
+            "range": argument.range,
+            "target": lsp::DocumentURI { path },
+        })
+    })
+}
+
+/// File-level lenses that show the shell dialect and apply every Shellcheck fix.
+fn code_lenses(document: &db::Document, uri: &lsp::DocumentURI) -> Json {
+    let position = lsp::Position { line: 0, character: 0 };
+    let range = lsp::Range { start: position, end: position };
+    let lens = |title: &str, command: &str| {
+        json!({
+            "range": range,
+            "command": { "title": title, "command": command, "arguments": [uri] },
+        })
+    };
+    let shell = format!("Shell: {}", document.info.shell.name());
+    let mut lenses = vec![lens(&shell, COMMAND_SHOW_STATUS)];
+    if !shellcheck_fix_edits(&document.info).is_empty() {
+        lenses.push(lens("Apply all Shellcheck fixes", COMMAND_APPLY_FIXES));
+    }
+    Json::Array(lenses)
+}
+
+fn document_links(document: &db::Document, path: &Path) -> Json {
+    let directory = path.parent().unwrap_or(Path::new("/"));
+    let arguments = document.info.calls.iter().flat_map(|call| {
+        let source = paths::is_source(&document.info, call);
+        (call.arguments.iter().enumerate())
+            .filter(move |&(index, argument)| {
+                if source {
+                    index == 0
+                }
+                else {
+                    paths::is_literal_absolute(argument.view.string(&document.text))
+                }
+            })
+            .map(|(_, &argument)| argument)
+    });
+    arguments.filter_map(|argument| document_link(document, argument, directory)).collect()
+}
+
 fn format(
     text: &str,
     range: lsp::Range,
@@ -445,6 +975,21 @@ fn format(
     Ok(json!([]))
 }
 
+/// Get the document's semantic tokens, as an edit to the previous result if possible.
+fn semantic_tokens(document: &mut db::Document, previous_result_id: Option<&str>) -> Json {
+    let data = lsp::encode_semantic_tokens(&document.info.tokens.data);
+    let result_id = document.revision.to_string();
+    let result = match &document.sent_tokens {
+        Some(sent) if previous_result_id == Some(sent.result_id.as_str()) => {
+            let edit = lsp::semantic_tokens_edit(&sent.data, &data);
+            json!({ "resultId": result_id, "edits": [edit] })
+        }
+        _ => json!({ "resultId": result_id, "data": data }),
+    };
+    document.sent_tokens = Some(db::SentTokens { result_id, data });
+    result
+}
+
 fn initialize(server: &mut Server, params: lsp::InitializeParams) -> Json {
     if std::mem::replace(&mut server.initialized, true) {
         eprintln!("[debug] Received initialize request when initialized");
@@ -452,6 +997,14 @@ fn initialize(server: &mut Server, params: lsp::InitializeParams) -> Json {
     if let Some(settings) = params.settings {
         server.settings = settings;
     }
+    server.client = params.capabilities;
+    let folder_uris: Vec<String> = match params.workspace_folders {
+        Some(folders) => folders.into_iter().map(|folder| folder.uri).collect(),
+        None => params.root_uri.into_iter().collect(),
+    };
+    server.workspace_folders = (folder_uris.iter())
+        .filter_map(|uri| lsp::DocumentURI::parse(uri).map(|uri| uri.path))
+        .collect();
     if server.settings.integrate.shellcheck.enable && !external::exists("shellcheck") {
         server.settings.integrate.shellcheck.enable = false;
     }
@@ -480,6 +1033,88 @@ fn parameter_hints(params: &[db::Location], range: lsp::Range) -> impl Iterator<
         })
 }
 
+/// The name of an annotated parameter: the first word of the annotation, which may be followed by
+/// a colon to separate it from the description, as in `##@ param name: Description`.
+fn parameter_name(annotation: &str) -> Option<&str> {
+    let word = annotation.split_whitespace().next()?;
+    Some(word.strip_suffix(':').unwrap_or(word))
+}
+
+/// Whether `argument` is a lone expansion of a variable called `name`, like `"$name"`, in which
+/// case a hint would only repeat it.
+fn names_parameter(argument: &str, name: &str) -> bool {
+    let variable = argument.trim_matches('"').trim_start_matches('$');
+    let variable = variable.strip_prefix('{').and_then(|v| v.strip_suffix('}')).unwrap_or(variable);
+    variable.eq_ignore_ascii_case(name)
+}
+
+/// Label the arguments of calls to annotated functions with the names of their parameters.
+fn argument_hints<'a>(
+    document: &'a db::Document,
+    range: lsp::Range,
+) -> impl Iterator<Item = Json> + 'a {
+    let info = &document.info;
+    (info.calls.iter())
+        .filter_map(|call| match info.symbols[call.id].kind {
+            db::SymbolKind::Function(id) => Some((call, &info.functions[id].parameters)),
+            _ => None,
+        })
+        .flat_map(move |(call, parameters)| {
+            (call.arguments.iter())
+                .map(|argument| (argument, argument.view.string(&document.text)))
+                // Arguments that expand to several words make the following ones ambiguous.
+                .take_while(|(_, text)| !text.contains("$@") && !text.contains("$*"))
+                .zip(parameters.iter().map(|param| param.view.string(&document.text)))
+                .filter(move |((argument, _), _)| range.contains(argument.range.start))
+                .filter_map(|((argument, text), annotation)| {
+                    let name = parameter_name(annotation)?;
+                    (!names_parameter(text, name)).then(|| {
+                        json!({
+                            "position": argument.range.start,
+                            "label": format!("{name}:"),
+                            "kind": 2, // Parameter
+                            "paddingRight": true,
+                        })
+                    })
+                })
+        })
+}
+
+/// Show the executables that external commands resolve to, and the files that `source` and `.`
+/// statements read, after the command name and the file argument respectively.
+fn resolution_hints(
+    document: &db::Document,
+    path: &Path,
+    range: lsp::Range,
+    settings: &Settings,
+) -> Vec<Json> {
+    let directory = path.parent().unwrap_or(Path::new("/"));
+    let mut executables: HashMap<&str, Option<PathBuf>> = HashMap::new();
+    let hint = |position: lsp::Position, resolved: Option<&Path>| {
+        let label = resolved.map_or(Cow::Borrowed("not found"), |path| path.to_string_lossy());
+        json!({ "position": position, "label": label, "paddingLeft": true })
+    };
+    let mut hints = Vec::new();
+    for call in document.info.calls.iter().filter(|call| range.contains(call.name.range.start)) {
+        let symbol = &document.info.symbols[call.id];
+        if settings.inlay_hints.command_paths
+            && matches!(symbol.kind, db::SymbolKind::Command)
+            && !symbol.name.contains('/')
+        {
+            let executable = (executables.entry(&symbol.name))
+                .or_insert_with(|| find_executable(&symbol.name, settings));
+            hints.push(hint(call.name.range.end, executable.as_deref()));
+        }
+        if settings.inlay_hints.sourced_files && paths::is_source(&document.info, call) {
+            if let Some(&argument) = call.arguments.first() {
+                let file = paths::evaluate(document, argument, directory);
+                hints.push(hint(argument.range.end, file.as_deref().filter(|file| file.is_file())));
+            }
+        }
+    }
+    hints
+}
+
 fn handle_request(server: &mut Server, method: &str, params: Json) -> Result<Json, rpc::Error> {
     match method {
         "initialize" => Ok(initialize(server, from_value(params)?)),
@@ -492,8 +1127,28 @@ fn handle_request(server: &mut Server, method: &str, params: Json) -> Result<Jso
         "textDocument/hover" => {
             let params: lsp::PositionParams = from_value(params)?;
             let document = &server.db.documents[document_id(&server.db, &params.document)?];
-            find_symbol(&document.info, params.position)
-                .map_or(Ok(Json::Null), |symbol| symbol_hover(document, symbol, &server.settings))
+            let explanations = shellcheck_hover(document, params.position);
+            let symbol = find_symbol(&document.info, params.position);
+            let syntax = syntax_hover(document, params.position, symbol.is_some());
+            // The parser reads reserved words it does not handle, like `until`, as commands.
+            match symbol.filter(|_| syntax.is_none()) {
+                Some(symbol) => {
+                    let explanations = explanations.map(|(markdown, _)| markdown);
+                    symbol_hover(document, symbol, explanations, &server.settings)
+                }
+                None => {
+                    let hover = match (syntax, explanations) {
+                        (Some((syntax, range)), Some((explanations, _))) => {
+                            Some((format!("{syntax}\n\n---\n\n{explanations}"), range))
+                        }
+                        (syntax, explanations) => syntax.or(explanations),
+                    };
+                    Ok(hover.map_or(Json::Null, |(markdown, range)| {
+                        let contents = lsp::MarkupContent::markdown(markdown);
+                        json!({ "contents": contents, "range": range })
+                    }))
+                }
+            }
         }
         "textDocument/definition" => {
             let params: lsp::PositionParams = from_value(params)?;
@@ -513,23 +1168,33 @@ fn handle_request(server: &mut Server, method: &str, params: Json) -> Result<Jso
             Ok(Json::Array(collect_references(document, params.position, |r| json!(r))))
         }
         "textDocument/inlayHint" => {
-            let lsp::DocumentIdentifierRangeParams { document, range } = from_value(params)?;
-            let document = get_document(&server.db, &document)?;
-            Ok((document.info.functions.underlying.iter())
+            let lsp::DocumentIdentifierRangeParams { document: id, range } = from_value(params)?;
+            let document = get_document(&server.db, &id)?;
+            let mut hints: Vec<Json> = (document.info.functions.underlying.iter())
                 .map(|function| function.parameters.as_slice())
                 .chain(document.info.script_parameters.iter().map(Vec::as_slice))
                 .flat_map(|params| parameter_hints(params, range))
-                .collect())
+                .collect();
+            if server.settings.inlay_hints.argument_names {
+                hints.extend(argument_hints(document, range));
+            }
+            hints.extend(resolution_hints(document, &id.uri.path, range, &server.settings));
+            Ok(Json::Array(hints))
         }
         "textDocument/prepareRename" => {
             let params: lsp::PositionParams = from_value(params)?;
             let document = get_document(&server.db, &params.document)?;
-            let mut references = find_references(&document.info, params.position);
-            Ok(references.next().map_or(Json::Null, |reference| json!(reference.range)))
+            let reference = rename_target(&document.info, params.position)?;
+            Ok(reference.map_or(Json::Null, |reference| json!(reference.reference.range)))
         }
         "textDocument/rename" => {
             let params: lsp::RenameParams = from_value(params)?;
             let document = get_document(&server.db, &params.position_params.document)?;
+            let Some(target) = rename_target(&document.info, params.position_params.position)?
+            else {
+                return Ok(Json::Null);
+            };
+            check_new_name(&document.info, target.id, &params.new_name)?;
             let edit = |r: lsp::Reference| json!({ "range": r.range, "newText": params.new_name });
             let edits = collect_references(document, params.position_params.position, edit);
             Ok(json!({ "changes": { params.position_params.document.uri.to_string(): edits } }))
@@ -538,21 +1203,40 @@ fn handle_request(server: &mut Server, method: &str, params: Json) -> Result<Jso
             let params: lsp::PositionParams = from_value(params)?;
             let document = get_document(&server.db, &params.document)?;
             let line = get_line(&document.text, params.position.line)?;
-            let line_prefix = &line[..params.position.character as usize];
-            let (offset, kind) = determine_completion_kind(line_prefix, params.position);
-            let prefix = &line_prefix[offset..];
-            let start = lsp::Position { line: params.position.line, character: offset as u32 };
-            let range = lsp::Range { start, end: params.position };
-
-            match kind {
-                lsp::CompletionItemKind::Variable => {
-                    Ok(variable_completions(document, range, prefix))
-                }
-                lsp::CompletionItemKind::Function => {
-                    Ok(function_completions(document, range, prefix))
+            let (cache, settings) = (&mut server.command_options, &server.settings);
+            let mut options = |name: &str| {
+                (cache.entry(String::from(name)))
+                    .or_insert_with(|| command_options(document.info.shell, name, settings).into())
+                    .clone()
+            };
+            Ok(json!(complete::completions(
+                document,
+                &params.document.uri,
+                line,
+                params.position,
+                server.client.text_document.completion.completion_item.snippet_support,
+                &mut options
+            )))
+        }
+        "completionItem/resolve" => {
+            let mut item = params;
+            // Keywords, snippets, paths, and options are complete without being resolved.
+            if item["data"].is_null() {
+                return Ok(item);
+            }
+            let data: lsp::CompletionData = from_value(item["data"].clone())?;
+            let id = lsp::DocumentIdentifier { uri: data.uri.clone() };
+            let document = get_document(&server.db, &id)?;
+            if let Some(symbol) = complete::resolve_symbol(&document.info, &data) {
+                if matches!(symbol.kind, db::SymbolKind::Command) {
+                    if let Some(path) = find_executable(&symbol.name, &server.settings) {
+                        item["detail"] = json!(path.display().to_string());
+                    }
                 }
-                _ => Err(rpc::Error::internal_error("completion failure")),
+                item["documentation"] =
+                    json!(completion_markup(document, symbol, &server.settings)?);
             }
+            Ok(item)
         }
         "textDocument/formatting" => {
             let params: lsp::FormattingParams = from_value(params)?;
@@ -579,19 +1263,75 @@ fn handle_request(server: &mut Server, method: &str, params: Json) -> Result<Jso
         "textDocument/codeAction" => {
             let params: lsp::DocumentIdentifierRangeParams = from_value(params)?;
             let document = get_document(&server.db, &params.document)?;
-            Ok((document.info.actions.iter())
+            let actions: Vec<&db::Action> = (document.info.actions.iter())
                 .filter(|action| {
                     action.range.contains_range(params.range)
                         || params.range.contains_range(action.range)
                 })
+                .collect();
+            Ok((actions.iter())
                 .map(|action| code_action(&document.text, &params.document.uri, action))
+                .chain(command_actions(&params.document.uri, &actions))
                 .chain(action_insert_path(&params, document, &server.settings))
+                .chain((find_symbol(&document.info, params.range.start).into_iter())
+                    .flat_map(|reference| {
+                        (actions::inline_variable(document, reference).into_iter())
+                            .chain(actions::annotate_function(document, reference))
+                    })
+                    .chain(actions::extract_variable(document, params.range))
+                    .chain(actions::extract_function(document, params.range))
+                    .chain(actions::annotate_script(document, params.range))
+                    .map(|action| code_action(&document.text, &params.document.uri, &action)))
                 .collect())
         }
         "textDocument/semanticTokens/full" => {
+            let params: lsp::DocumentIdentifierParams = from_value(params)?;
+            let id = document_id(&server.db, &params.document)?;
+            Ok(semantic_tokens(&mut server.db.documents[id], None))
+        }
+        "textDocument/semanticTokens/full/delta" => {
+            let params: lsp::SemanticTokensDeltaParams = from_value(params)?;
+            let id = document_id(&server.db, &params.document)?;
+            Ok(semantic_tokens(&mut server.db.documents[id], Some(&params.previous_result_id)))
+        }
+        "textDocument/semanticTokens/range" => {
+            let params: lsp::DocumentIdentifierRangeParams = from_value(params)?;
+            let document = get_document(&server.db, &params.document)?;
+            let tokens = document.info.tokens.range(params.range);
+            Ok(json!({ "data": lsp::encode_semantic_tokens(tokens) }))
+        }
+        "textDocument/diagnostic" => {
+            let params: lsp::DocumentDiagnosticParams = from_value(params)?;
+            let previous = params.previous_result_id.as_deref();
+            match document_id(&server.db, &params.document) {
+                Ok(id) => Ok(open_document_report(&server.db.documents[id], previous)),
+                Err(_) => Ok(unopened_document_report(
+                    &params.document.uri.path,
+                    previous,
+                    &server.settings,
+                    server.generation,
+                    &mut server.unopened_reports,
+                )?),
+            }
+        }
+        "workspace/executeCommand" => execute_command(server, from_value(params)?),
+        "workspace/willRenameFiles" => {
+            let params: lsp::RenameFilesParams = from_value(params)?;
+            Ok(rename_files(server, &params.files))
+        }
+        "workspace/diagnostic" => {
+            let params: lsp::WorkspaceDiagnosticParams = from_value(params)?;
+            Ok(workspace_diagnostics(server, params))
+        }
+        "textDocument/documentLink" => {
+            let params: lsp::DocumentIdentifierParams = from_value(params)?;
+            let document = get_document(&server.db, &params.document)?;
+            Ok(document_links(document, &params.document.uri.path))
+        }
+        "textDocument/codeLens" => {
             let params: lsp::DocumentIdentifierParams = from_value(params)?;
             let document = get_document(&server.db, &params.document)?;
-            Ok(json!({ "data": document.info.tokens }))
+            Ok(code_lenses(document, &params.document.uri))
         }
         "textDocument/documentSymbol" => {
             let params: lsp::DocumentIdentifierParams = from_value(params)?;
@@ -612,8 +1352,10 @@ fn handle_notification(server: &mut Server, method: &str, params: Json) -> Resul
         "textDocument/didOpen" => {
             let params: lsp::DidOpenDocumentParams = from_value(params)?;
             let mut document = db::Document::new(params.document.text);
-            analyze(&mut document, &server.settings);
-            push_diagnostics(&mut server.output, &params.document.uri, &document.info)?;
+            analyze(&mut document, server.db.next_revision(), &server.settings);
+            if server.client.text_document.diagnostic.is_none() {
+                push_diagnostics(&mut server.output, &params.document.uri, &document.info)?;
+            }
             server.db.open(params.document.uri.path, document);
             Ok(())
         }
@@ -625,17 +1367,29 @@ fn handle_notification(server: &mut Server, method: &str, params: Json) -> Resul
         "textDocument/didChange" => {
             let params: lsp::DidChangeDocumentParams = from_value(params)?;
             let id = document_id(&server.db, &params.document.identifier)?;
+            let revision = server.db.next_revision();
             let document = &mut server.db.documents[id];
             for change in params.changes {
                 document.edit(change.range, &change.text);
             }
-            analyze(document, &server.settings);
-            push_diagnostics(&mut server.output, &params.document.identifier.uri, &document.info)?;
+            analyze(document, revision, &server.settings);
+            if server.client.text_document.diagnostic.is_none() {
+                let uri = &params.document.identifier.uri;
+                push_diagnostics(&mut server.output, uri, &document.info)?;
+            }
+            Ok(())
+        }
+        "workspace/didRenameFiles" => {
+            let params: lsp::RenameFilesParams = from_value(params)?;
+            for rename in params.files {
+                server.db.rename(&rename.old_uri.path, &rename.new_uri.path);
+            }
             Ok(())
         }
         "workspace/didChangeConfiguration" => {
             let params: lsp::DidChangeConfigurationParams = from_value(params)?;
             server.settings = params.settings.shell;
+            refresh(server)?;
             Ok(())
         }
         _ => {
@@ -669,9 +1423,26 @@ fn deserialization_error(error: serde_json::Error) -> rpc::Response {
     rpc::Response::error(None, rpc::Error::new(code, error.to_string()))
 }
 
+/// Handle a response to a request that was sent to the client.
+fn handle_response(response: &Json) {
+    if let Some(error) = response.get("error") {
+        eprintln!("[debug] Client request failed: {error}");
+    }
+    else if response["result"]["applied"] == json!(false) {
+        eprintln!("[debug] Client did not apply edit: {}", response["result"]["failureReason"]);
+    }
+}
+
 fn handle_message(server: &mut Server, message: &str) -> Option<rpc::Response> {
-    match serde_json::from_str(message) {
-        Ok(request) => dispatch_handle_request(server, request),
+    match serde_json::from_str::<Json>(message) {
+        Ok(message) if message.get("method").is_none() && message.get("id").is_some() => {
+            handle_response(&message);
+            None
+        }
+        Ok(message) => match from_value(message) {
+            Ok(request) => dispatch_handle_request(server, request),
+            Err(error) => Some(deserialization_error(error)),
+        },
         Err(error) => Some(deserialization_error(error)),
     }
 }
@@ -680,8 +1451,14 @@ pub fn run(cmdline: Cmdline) -> ExitCode {
     let mut server = Server {
         db: db::Database::default(),
         settings: cmdline.settings,
+        client: lsp::ClientCapabilities::default(),
+        workspace_folders: Vec::new(),
+        command_options: HashMap::new(),
+        unopened_reports: HashMap::new(),
+        generation: 0,
         initialized: false,
         exit_code: None,
+        request_id: 0,
         input: &mut std::io::stdin().lock(),
         output: &mut std::io::stdout().lock(),
     };
@@ -720,3 +1497,57 @@ pub fn run(cmdline: Cmdline) -> ExitCode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Settings;
+    use crate::{db, lsp, parse};
+
+    fn info(text: &str) -> db::DocumentInfo {
+        let mut settings = Settings::default();
+        settings.environment.variables = false;
+        settings.environment.executables = false;
+        parse::parse(text, &settings)
+    }
+
+    fn position(line: u32, character: u32) -> lsp::Position {
+        lsp::Position { line, character }
+    }
+
+    #[test]
+    fn rename_target() {
+        let info = info("#!/bin/bash\nf() { local x=$1; echo \"$x\"; }\nf\nls $?\n");
+        let target = |line, character| super::rename_target(&info, position(line, character));
+        assert!(matches!(target(1, 0), Ok(Some(_))));
+        assert!(matches!(target(1, 12), Ok(Some(_))));
+        assert!(matches!(target(2, 0), Ok(Some(_))));
+        assert!(matches!(target(1, 3), Ok(None)));
+        assert!(target(1, 15).is_err());
+        assert!(target(1, 18).is_err());
+        assert!(target(3, 0).is_err());
+        assert!(target(3, 4).is_err());
+    }
+
+    #[test]
+    fn check_new_name() {
+        let check = |text: &str, line, character, name: &str| {
+            let info = info(text);
+            let target = super::find_symbol(&info, position(line, character)).unwrap();
+            super::check_new_name(&info, target.id, name).is_ok()
+        };
+        let bash = "#!/bin/bash\nf() { :; }\ng() { :; }\nx=1\n";
+        assert!(check(bash, 1, 0, "h"));
+        assert!(check(bash, 1, 0, "a-b"));
+        assert!(!check(bash, 1, 0, "g"));
+        assert!(!check(bash, 1, 0, "a b; rm -rf x"));
+        assert!(!check(bash, 1, 0, "a*"));
+        assert!(!check(bash, 1, 0, "'a'"));
+        assert!(!check(bash, 1, 0, "[["));
+        assert!(!check(bash, 1, 0, "time"));
+        assert!(!check(bash, 1, 0, "if"));
+        assert!(check(bash, 3, 0, "y"));
+        assert!(!check(bash, 3, 0, "1x"));
+        assert!(!check("#!/bin/sh\nf() { :; }\n", 1, 0, "a-b"));
+        assert!(check("#!/bin/sh\nf() { :; }\n", 1, 0, "time"));
+    }
+}
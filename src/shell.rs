@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
 pub enum Shell {
     #[default]
@@ -22,6 +24,12 @@ impl Shell {
     }
 }
 
+/// Shells with Bourne shell syntax.
+pub const BOURNE: &[Shell] = &[Shell::Posix, Shell::Bash, Shell::Zsh, Shell::Ksh];
+
+/// Shells that extend POSIX with `select`, arithmetic `for` loops, and similar constructs.
+pub const EXTENDED: &[Shell] = &[Shell::Bash, Shell::Zsh, Shell::Ksh];
+
 pub fn parse_shell_name(str: &str) -> Result<Shell, String> {
     match str {
         "sh" | "dash" => Ok(Shell::Posix),
@@ -35,14 +43,37 @@ pub fn parse_shell_name(str: &str) -> Result<Shell, String> {
     }
 }
 
-pub fn parse_shebang(shebang: &str) -> Result<Shell, String> {
-    let str = shebang.trim_ascii().strip_prefix('/').ok_or("Expected an absolute path")?;
+/// Find the byte range of the interpreter name in a shebang, like `bash` in `/usr/bin/env bash`.
+pub fn shebang_interpreter(shebang: &str) -> Result<Range<usize>, String> {
+    let trimmed = shebang.trim_ascii();
+    let str = trimmed.strip_prefix('/').ok_or("Expected an absolute path")?;
     let shell = str
         .strip_prefix("usr/bin/env ")
         .or_else(|| str.strip_prefix("usr/bin/"))
         .or_else(|| str.strip_prefix("bin/"))
         .ok_or("Expected /bin/ or /usr/bin/")?;
-    parse_shell_name(shell.split_whitespace().next().unwrap_or(shell))
+    let name = shell.split_whitespace().next().unwrap_or(shell);
+    let start = (shebang.len() - shebang.trim_ascii_start().len()) + (trimmed.len() - shell.len());
+    Ok(start..start + name.len())
+}
+
+pub fn parse_shebang(shebang: &str) -> Result<Shell, String> {
+    parse_shell_name(&shebang[shebang_interpreter(shebang)?])
+}
+
+/// Words that are reserved when they appear as command names, as specified by POSIX.
+pub const RESERVED_WORDS: &[&str] = &[
+    "!", "{", "}", "case", "do", "done", "elif", "else", "esac", "fi", "for", "if", "in", "then",
+    "until", "while",
+];
+
+/// Words that are reserved by Bash, Zsh, and Ksh in addition to `RESERVED_WORDS`.
+pub const EXTENDED_RESERVED_WORDS: &[&str] = &["[[", "]]", "coproc", "function", "select", "time"];
+
+/// Whether `word` is reserved when it appears as a command name in `shell`.
+pub fn is_reserved_word(word: &str, shell: Shell) -> bool {
+    RESERVED_WORDS.contains(&word)
+        || (EXTENDED.contains(&shell) && EXTENDED_RESERVED_WORDS.contains(&word))
 }
 
 #[rustfmt::skip]
@@ -78,4 +109,10 @@ mod tests {
         assert_eq!(super::parse_shebang("bash"), err);
         assert_eq!(super::parse_shebang(""), err);
     }
+
+    #[test]
+    fn shebang_interpreter() {
+        assert_eq!(super::shebang_interpreter("/bin/sh -e"), Ok(5..7));
+        assert_eq!(super::shebang_interpreter(" /usr/bin/env sh"), Ok(14..16));
+    }
 }
@@ -0,0 +1,504 @@
+use crate::lex::{self, TokenKind};
+use crate::shell::{Shell, BOURNE, EXTENDED};
+use crate::{db, lsp};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SyntaxKind {
+    ReservedWord,
+    ControlOperator,
+    Redirection,
+}
+
+/// Documentation for a reserved word or an operator.
+pub struct Syntax {
+    pub text: &'static str,
+    pub kind: SyntaxKind,
+    pub description: &'static str,
+    /// The shells that support this syntax. `BOURNE` means that it is specified by POSIX.
+    pub shells: &'static [Shell],
+}
+
+/// The text, description, and supporting shells of a piece of syntax.
+type Entry = (&'static str, &'static str, &'static [Shell]);
+
+const BASH_ZSH: &[Shell] = &[Shell::Bash, Shell::Zsh];
+
+const RESERVED_WORDS: &[Entry] = &[
+    ("if", "Run commands if a condition succeeds: `if condition; then ...; fi`.", BOURNE),
+    ("then", "Begin the commands to run if the preceding condition succeeded.", BOURNE),
+    ("else", "Begin the commands to run if none of the preceding conditions succeeded.", BOURNE),
+    ("elif", "Test another condition if the preceding ones failed.", BOURNE),
+    ("fi", "End an `if` statement.", BOURNE),
+    ("for", "Run commands once for each word: `for name in words; do ...; done`.", BOURNE),
+    ("while", "Run commands as long as a condition succeeds.", BOURNE),
+    ("until", "Run commands as long as a condition fails.", BOURNE),
+    ("do", "Begin the body of a loop.", BOURNE),
+    ("done", "End the body of a loop.", BOURNE),
+    ("in", "Begin the words of a `for` loop, or the patterns of a `case` statement.", BOURNE),
+    ("case", "Run the commands of the first pattern that matches a word.", BOURNE),
+    ("esac", "End a `case` statement.", BOURNE),
+    ("!", "Negate the exit status of a pipeline.", BOURNE),
+    ("{", "Begin a group of commands that runs in the current shell.", BOURNE),
+    ("}", "End a group of commands.", BOURNE),
+    ("[[", "Begin a conditional expression, without word splitting or globbing.", EXTENDED),
+    ("]]", "End a conditional expression.", EXTENDED),
+    ("function", "Define a function: `function name { ...; }`.", EXTENDED),
+    ("select", "Prompt for one of the given words, and run commands for each choice.", EXTENDED),
+    ("time", "Report the time taken to run a pipeline.", EXTENDED),
+];
+
+const CONTROL_OPERATORS: &[Entry] = &[
+    ("&&", "Run the next command only if the previous one succeeded.", BOURNE),
+    ("||", "Run the next command only if the previous one failed.", BOURNE),
+    (
+        "|",
+        "Connect the standard output of the previous command to the standard input of the next \
+         one.",
+        BOURNE,
+    ),
+    (
+        "|&",
+        "Pipe both the standard output and the standard error of the previous command, like \
+         `2>&1 |`. In Ksh, it instead runs the previous command as a coprocess.",
+        BASH_ZSH,
+    ),
+    ("&", "Run the previous command asynchronously, in the background.", BOURNE),
+    (";", "Run the previous command, then the next one.", BOURNE),
+    (";;", "End the commands of a `case` pattern.", BOURNE),
+    (
+        ";&",
+        "End the commands of a `case` pattern, and fall through to the commands of the next one.",
+        EXTENDED,
+    ),
+    (
+        ";;&",
+        "End the commands of a `case` pattern, and continue testing the following patterns.",
+        &[Shell::Bash],
+    ),
+];
+
+const REDIRECTIONS: &[Entry] = &[
+    ("<", "Read standard input, or file descriptor `n` in `n<file`, from a file.", BOURNE),
+    (
+        ">",
+        "Write standard output, or file descriptor `n` in `n>file`, to a file, truncating it. \
+         Fails if the file exists and `set -C` is in effect.",
+        BOURNE,
+    ),
+    (">>", "Append standard output, or file descriptor `n` in `n>>file`, to a file.", BOURNE),
+    (">|", "Write standard output to a file, truncating it even if `set -C` is in effect.", BOURNE),
+    (
+        "<>",
+        "Open a file for both reading and writing as standard input, or as file descriptor `n` \
+         in `n<>file`.",
+        BOURNE,
+    ),
+    (
+        "<<",
+        "Read standard input from the following lines, up to a line containing only the \
+         delimiter. Quoting the delimiter prevents expansions in the here-document.",
+        BOURNE,
+    ),
+    (
+        "<<-",
+        "Read standard input from a here-document, removing leading tab characters from its \
+         lines and the delimiter line.",
+        BOURNE,
+    ),
+    ("<<<", "Read standard input from a single word, followed by a new line.", EXTENDED),
+    (
+        "<&",
+        "Duplicate an input file descriptor: `n<&m` makes `n` (standard input by default) a copy \
+         of `m`, and `n<&-` closes `n`.",
+        BOURNE,
+    ),
+    (
+        ">&",
+        "Duplicate an output file descriptor: `n>&m` makes `n` (standard output by default) a \
+         copy of `m`, as in `2>&1`, and `n>&-` closes `n`.",
+        BOURNE,
+    ),
+    ("&>", "Write both standard output and standard error to a file, like `>file 2>&1`.", BASH_ZSH),
+    (
+        "&>>",
+        "Append both standard output and standard error to a file, like `>>file 2>&1`.",
+        BASH_ZSH,
+    ),
+];
+
+fn entries() -> impl Iterator<Item = Syntax> {
+    let tables = [
+        (SyntaxKind::ReservedWord, RESERVED_WORDS),
+        (SyntaxKind::ControlOperator, CONTROL_OPERATORS),
+        (SyntaxKind::Redirection, REDIRECTIONS),
+    ];
+    (tables.into_iter()).flat_map(|(kind, entries)| {
+        (entries.iter())
+            .map(move |&(text, description, shells)| Syntax { text, kind, description, shells })
+    })
+}
+
+/// Find the documentation for a reserved word or an operator.
+pub fn find(text: &str) -> Option<Syntax> {
+    entries().find(|syntax| syntax.text == text)
+}
+
+/// Find the longest documented operator in `line` that covers the character at `character`,
+/// along with its character range.
+fn operator_at(line: &str, character: u32) -> Option<(Syntax, std::ops::Range<u32>)> {
+    let chars: Vec<char> = line.chars().collect();
+    let cursor = character as usize;
+    let matches = |start: usize, text: &str| {
+        let width = text.chars().count();
+        chars.get(start..start + width).is_some_and(|slice| slice.iter().copied().eq(text.chars()))
+    };
+    (entries())
+        .filter(|syntax| syntax.kind != SyntaxKind::ReservedWord)
+        .filter_map(|syntax| {
+            let width = syntax.text.chars().count();
+            let start = (cursor.saturating_sub(width - 1)..=cursor)
+                .find(|&start| matches(start, syntax.text))?;
+            Some((syntax, start as u32..(start + width) as u32))
+        })
+        .max_by_key(|(_, range)| range.len())
+}
+
+const OPERATOR_KINDS: &[TokenKind] = &[
+    TokenKind::Less,
+    TokenKind::LessLess,
+    TokenKind::LessLessDash,
+    TokenKind::LessAnd,
+    TokenKind::LessGreat,
+    TokenKind::Great,
+    TokenKind::GreatGreat,
+    TokenKind::GreatAnd,
+    TokenKind::GreatPipe,
+    TokenKind::Pipe,
+    TokenKind::PipePipe,
+    TokenKind::And,
+    TokenKind::AndAnd,
+    TokenKind::Semi,
+    TokenKind::SemiSemi,
+];
+
+/// The control operators after which a new command begins.
+const SEPARATOR_KINDS: &[TokenKind] = &[
+    TokenKind::Pipe,
+    TokenKind::PipePipe,
+    TokenKind::And,
+    TokenKind::AndAnd,
+    TokenKind::Semi,
+    TokenKind::SemiSemi,
+];
+
+/// Skip the here-document bodies that begin at `start` in `text`, if any.
+fn skip_heredocs(lexer: &mut lex::Lexer, text: &str, heredocs: &[lsp::Range], start: lex::Token) {
+    let line = start.range.end.line;
+    let Some(mut end) = heredocs.iter().find(|body| body.start.line == line).map(|body| body.end)
+    else {
+        return;
+    };
+    while let Some(body) = heredocs.iter().find(|body| body.start == end) {
+        end = body.end;
+    }
+    let width: usize = (text[start.view.end as usize..].split_inclusive('\n'))
+        .take((end.line - line) as usize)
+        .map(str::len)
+        .sum();
+    lexer.skip_region(db::View { start: start.view.end, end: start.view.end + width as u32 });
+}
+
+/// Collect the unquoted operator tokens, parentheses, braces, and words on `line` of `text`.
+fn line_tokens(text: &str, heredocs: &[lsp::Range], line: u32) -> Vec<lex::Token> {
+    let mut lexer = lex::Lexer::new(text);
+    let mut tokens = Vec::new();
+    let mut quotes: Vec<TokenKind> = Vec::new();
+    let mut previous: Option<TokenKind> = None;
+    while let Some(token) = lexer.next() {
+        if token.range.start.line > line {
+            break;
+        }
+        let quoted = quotes.last() == Some(&TokenKind::DoubleQuote);
+        match token.kind {
+            TokenKind::NewLine => skip_heredocs(&mut lexer, text, heredocs, token),
+            TokenKind::DoubleQuote | TokenKind::BackQuote if quotes.last() == Some(&token.kind) => {
+                quotes.pop();
+            }
+            TokenKind::DoubleQuote | TokenKind::BackQuote => quotes.push(token.kind),
+            TokenKind::ParenOpen if !quoted || previous == Some(TokenKind::Dollar) => {
+                quotes.push(token.kind);
+            }
+            TokenKind::ParenClose if quotes.last() == Some(&TokenKind::ParenOpen) => {
+                quotes.pop();
+            }
+            _ => {}
+        }
+        let collected = match token.kind {
+            TokenKind::Word | TokenKind::ParenOpen | TokenKind::ParenClose => true,
+            TokenKind::BraceOpen => previous != Some(TokenKind::Dollar),
+            kind => kind == TokenKind::BraceClose || OPERATOR_KINDS.contains(&kind),
+        };
+        // A command substitution in double quotes begins an unquoted context.
+        let quoted = quotes.last() == Some(&TokenKind::DoubleQuote);
+        if collected && !quoted && token.range.start.line == line {
+            tokens.push(token);
+        }
+        previous = Some(token.kind);
+    }
+    tokens
+}
+
+/// Find the documented operator at `position` among the `tokens` of its line, along with its
+/// range. The range of a redirection includes its file descriptor number, as in `2>&1`.
+fn find_operator(
+    text: &str,
+    tokens: &[lex::Token],
+    position: lsp::Position,
+) -> Option<(Syntax, lsp::Range)> {
+    let is_operator = |token: &lex::Token| OPERATOR_KINDS.contains(&token.kind);
+    let is_number = |token: &lex::Token| {
+        token.kind == TokenKind::Word
+            && token.view.string(text).chars().all(|char| char.is_ascii_digit())
+    };
+    let adjacent = |a: &lex::Token, b: &lex::Token| a.range.end == b.range.start;
+    let mut index = (tokens.iter()).position(|token| {
+        token.range.start.character <= position.character
+            && position.character < token.range.end.character
+    })?;
+    let mut character = position.character;
+    // Hovering a file descriptor number describes the redirection that follows it.
+    let on_number = is_number(&tokens[index]);
+    if on_number {
+        index += 1;
+        character = tokens.get(index)?.range.start.character;
+    }
+    let operator = tokens.get(index)?;
+    if !is_operator(operator) || (on_number && !adjacent(&tokens[index - 1], operator)) {
+        return None;
+    }
+    // Operators like `&>` and `;;&` are lexed as several adjacent tokens.
+    let joined = |a: usize, b: usize| {
+        is_operator(&tokens[a]) && is_operator(&tokens[b]) && adjacent(&tokens[a], &tokens[b])
+    };
+    let mut first = index;
+    while first > 0 && joined(first - 1, first) {
+        first -= 1;
+    }
+    let mut last = index;
+    while last + 1 < tokens.len() && joined(last, last + 1) {
+        last += 1;
+    }
+    let start = tokens[first].range.start;
+    let run = &text[tokens[first].view.start as usize..tokens[last].view.end as usize];
+    let (syntax, columns) = operator_at(run, character - start.character)?;
+    let mut range = lsp::Range {
+        start: start.horizontal_offset(columns.start),
+        end: start.horizontal_offset(columns.end),
+    };
+    let number = (first.checked_sub(1).map(|previous| &tokens[previous]))
+        .filter(|number| is_number(number) && adjacent(number, &tokens[first]));
+    let has_number = syntax.kind == SyntaxKind::Redirection && !syntax.text.starts_with('&');
+    match number.filter(|_| has_number && columns.start == 0) {
+        Some(number) => range.start = number.range.start,
+        None if on_number => return None,
+        None => {}
+    }
+    Some((syntax, range))
+}
+
+/// Find the documented reserved word at `position` among the `tokens` of its line, along with
+/// its range. Reserved words are only recognized in command position, and `in` and `]]` only
+/// where their compound commands expect them.
+fn find_reserved_word(
+    text: &str,
+    tokens: &[lex::Token],
+    position: lsp::Position,
+) -> Option<(Syntax, lsp::Range)> {
+    let is_loop_keyword =
+        |token: &lex::Token| matches!(token.view.string(text), "for" | "select" | "case");
+    let mut command_position = true;
+    let mut expects_in = false;
+    let mut in_test = false;
+    for (index, token) in tokens.iter().enumerate() {
+        let word = token.view.string(text);
+        let reserved = match token.kind {
+            // `in` follows the name or word after `for`, `select`, or `case`.
+            TokenKind::Word if word == "in" => {
+                expects_in && {
+                    let previous = &tokens[index - 1];
+                    let between = &text[previous.view.end as usize..token.view.start as usize];
+                    !is_loop_keyword(previous) || !between.trim().is_empty()
+                }
+            }
+            TokenKind::Word if word == "]]" => in_test,
+            TokenKind::Word => {
+                command_position
+                    && find(word).is_some_and(|syntax| syntax.kind == SyntaxKind::ReservedWord)
+            }
+            // A brace only begins a group when it is followed by a blank.
+            TokenKind::BraceOpen => {
+                let next = text[token.view.end as usize..].chars().next();
+                command_position && next.is_none_or(char::is_whitespace)
+            }
+            TokenKind::BraceClose => command_position,
+            _ => false,
+        };
+        if reserved
+            && token.range.start.character <= position.character
+            && position.character < token.range.end.character
+        {
+            return Some((find(word)?, token.range));
+        }
+        if reserved {
+            expects_in = is_loop_keyword(token);
+            in_test = (in_test && word != "]]") || word == "[[";
+        }
+        else if token.kind != TokenKind::Word {
+            expects_in = false;
+        }
+        command_position = match token.kind {
+            TokenKind::Word => {
+                reserved
+                    && matches!(
+                        word,
+                        "if" | "then" | "else" | "elif" | "while" | "until" | "do" | "!" | "time"
+                    )
+            }
+            TokenKind::BraceOpen => reserved,
+            TokenKind::ParenOpen | TokenKind::ParenClose => true,
+            kind => SEPARATOR_KINDS.contains(&kind),
+        };
+    }
+    None
+}
+
+/// Find the documented reserved word or operator at `position` in `text`, along with its range.
+/// Syntax is looked up among the lexer tokens outside of quotes, comments, and the
+/// here-document bodies in `heredocs`.
+pub fn find_at(
+    text: &str,
+    heredocs: &[lsp::Range],
+    position: lsp::Position,
+) -> Option<(Syntax, lsp::Range)> {
+    let tokens = line_tokens(text, heredocs, position.line);
+    find_operator(text, &tokens, position).or_else(|| find_reserved_word(text, &tokens, position))
+}
+
+impl SyntaxKind {
+    pub fn describe(self) -> &'static str {
+        match self {
+            SyntaxKind::ReservedWord => "Reserved word",
+            SyntaxKind::ControlOperator => "Control operator",
+            SyntaxKind::Redirection => "Redirection",
+        }
+    }
+}
+
+impl Syntax {
+    /// Describe this syntax, with notes on its portability to `shell`.
+    pub fn markdown(&self, shell: Shell) -> String {
+        let mut markdown =
+            format!("# {} `{}`\n\n{}", self.kind.describe(), self.text, self.description);
+        if self.shells != BOURNE {
+            let names: Vec<&str> = self.shells.iter().map(|shell| shell.name()).collect();
+            markdown.push_str(&format!(
+                "\n\nNot specified by POSIX. Supported by: {}.",
+                names.join(", ")
+            ));
+            if !self.shells.contains(&shell) {
+                markdown.push_str(&format!("\n\n**Not supported by {}.**", shell.name()));
+            }
+        }
+        markdown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Settings;
+    use crate::lsp;
+    use crate::shell::Shell;
+    use std::ops::Range;
+
+    #[test]
+    fn operator_at() {
+        let find = |line: &str, character| {
+            super::operator_at(line, character).map(|(syntax, range)| (syntax.text, range))
+        };
+        assert_eq!(find("a 2>&1", 3), Some((">&", 3..5)));
+        assert_eq!(find("a &>> b", 2), Some(("&>>", 2..5)));
+        assert_eq!(find("a &>> b", 4), Some(("&>>", 2..5)));
+        assert_eq!(find("cat <<<word", 6), Some(("<<<", 4..7)));
+        assert_eq!(find("a && b", 3), Some(("&&", 2..4)));
+        assert_eq!(find("a && b", 0), None);
+    }
+
+    fn find_at(text: &str, line: u32, character: u32) -> Option<(&'static str, Range<u32>)> {
+        let heredocs = crate::parse::parse(text, &Settings::default()).heredocs;
+        let position = lsp::Position { line, character };
+        super::find_at(text, &heredocs, position)
+            .map(|(syntax, range)| (syntax.text, range.start.character..range.end.character))
+    }
+
+    #[test]
+    fn find_operator() {
+        let find = find_at;
+        assert_eq!(find("if a; then b; fi && :", 0, 18), Some(("&&", 17..19)));
+        assert_eq!(find("if a\nthen b; c\nfi", 1, 6), Some((";", 6..7)));
+        assert_eq!(find("a 2>&1", 0, 2), Some((">&", 2..5)));
+        assert_eq!(find("a 2>&1", 0, 3), Some((">&", 2..5)));
+        assert_eq!(find("a 2>&1", 0, 5), None);
+        assert_eq!(find("a &>> b", 0, 3), Some(("&>>", 2..5)));
+        assert_eq!(find("a 2 > b", 0, 2), None);
+        assert_eq!(find("a |& b", 0, 3), Some(("|&", 2..4)));
+        assert_eq!(find("echo \"a && b\" # c && d", 0, 8), None);
+        assert_eq!(find("echo \"a && b\" # c && d", 0, 19), None);
+        assert_eq!(find("echo \"$(a && b)\"", 0, 10), Some(("&&", 10..12)));
+        assert_eq!(find("cat <<E\na && b\nE\nc && d", 1, 2), None);
+        assert_eq!(find("cat <<E\na && b\nE\nc && d", 3, 2), Some(("&&", 2..4)));
+    }
+
+    #[test]
+    fn find_reserved_word() {
+        let find = find_at;
+        assert_eq!(find("if a; then b; else c; fi", 0, 0), Some(("if", 0..2)));
+        assert_eq!(find("if a; then b; else c; fi", 0, 7), Some(("then", 6..10)));
+        assert_eq!(find("if a; then b; else c; fi", 0, 14), Some(("else", 14..18)));
+        assert_eq!(find("if a; then b; else c; fi", 0, 23), Some(("fi", 22..24)));
+        assert_eq!(find("if a; then b\nelif c; then d; fi", 1, 1), Some(("elif", 0..4)));
+        assert_eq!(find("for x in a b; do c; done", 0, 1), Some(("for", 0..3)));
+        assert_eq!(find("for x in a b; do c; done", 0, 6), Some(("in", 6..8)));
+        assert_eq!(find("for x in a b; do c; done", 0, 14), Some(("do", 14..16)));
+        assert_eq!(find("for x in a b; do c; done", 0, 20), Some(("done", 20..24)));
+        assert_eq!(find("while a; do b; done", 0, 0), Some(("while", 0..5)));
+        assert_eq!(find("until a; do b; done", 0, 0), Some(("until", 0..5)));
+        assert_eq!(find("case \"$a\" in b) c ;; esac", 0, 2), Some(("case", 0..4)));
+        assert_eq!(find("case \"$a\" in b) c ;; esac", 0, 10), Some(("in", 10..12)));
+        assert_eq!(find("case \"$a\" in b) c ;; esac", 0, 22), Some(("esac", 21..25)));
+        assert_eq!(find("! a | b", 0, 0), Some(("!", 0..1)));
+        assert_eq!(find("{ a; }", 0, 0), Some(("{", 0..1)));
+        assert_eq!(find("{ a; }", 0, 5), Some(("}", 5..6)));
+        assert_eq!(find("[[ -n $a ]]", 0, 1), Some(("[[", 0..2)));
+        assert_eq!(find("[[ -n $a ]]", 0, 9), Some(("]]", 9..11)));
+        assert_eq!(find("function f { a; }", 0, 3), Some(("function", 0..8)));
+        assert_eq!(find("select a in b; do c; done", 0, 3), Some(("select", 0..6)));
+        assert_eq!(find("time a | b", 0, 3), Some(("time", 0..4)));
+        assert_eq!(find("echo if { } in ]]", 0, 5), None);
+        assert_eq!(find("echo if { } in ]]", 0, 8), None);
+        assert_eq!(find("echo if { } in ]]", 0, 10), None);
+        assert_eq!(find("echo if { } in ]]", 0, 12), None);
+        assert_eq!(find("echo if { } in ]]", 0, 15), None);
+        assert_eq!(find("echo \"if\" # fi", 0, 6), None);
+        assert_eq!(find("echo \"$(if a; then b; fi)\"", 0, 8), Some(("if", 8..10)));
+        assert_eq!(find("echo ${a} {b,c}; {a; }", 0, 17), None);
+    }
+
+    #[test]
+    fn markdown() {
+        let markdown = super::find("&>").unwrap().markdown(Shell::Posix);
+        assert!(markdown.starts_with("# Redirection `&>`"));
+        assert!(markdown.contains("**Not supported by POSIX shell.**"));
+        assert!(!super::find("&>").unwrap().markdown(Shell::Bash).contains("Not supported"));
+        assert!(!super::find("if").unwrap().markdown(Shell::Posix).contains("POSIX"));
+    }
+}